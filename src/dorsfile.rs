@@ -1,29 +1,274 @@
+use crate::env::{env_references, EnvSource, EnvTable, Scalar};
 use crate::error::{DorsError, Error};
+use indexmap::IndexMap;
 use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
 pub struct Dorsfile {
+    /// Stops the search for Dorsfiles above the workspace root
+    #[serde(default)]
+    pub root: bool,
+    #[serde(default)]
+    pub env: Vec<EnvTable>,
+    #[serde(default)]
+    pub path_prepend: Vec<PathBuf>,
     #[serde(default)]
-    pub env: Vec<HashMap<String, String>>,
+    pub path_append: Vec<PathBuf>,
+    /// Variables whose values are masked in task output
+    #[serde(default)]
+    pub secret_env: Vec<String>,
+    /// Directories outside of cargo's knowledge that are treated as workspace members
+    #[serde(default)]
+    pub extra_members: BTreeMap<String, PathBuf>,
+    /// Independent cargo workspaces that `run-from = "workspaces"` tasks are ran on
+    pub workspaces: Option<Workspaces>,
     #[serde(default)]
     pub task: HashMap<String, Task>,
 }
 
+#[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
+pub struct Workspaces {
+    pub paths: Vec<PathBuf>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 #[serde(rename_all = "kebab-case")]
 pub struct Task {
     #[serde(default)]
     pub run_from: Run,
     #[serde(default)]
+    pub relative_to: RelativeTo,
+    #[serde(default)]
     pub command: String,
-    pub before: Option<Vec<String>>,
-    pub after: Option<Vec<String>>,
+    pub before: Option<Vec<Dependency>>,
+    pub after: Option<Vec<Dependency>>,
     #[serde(flatten)]
     pub member_modifiers: Option<MemberModifiers>,
+    #[serde(default)]
+    pub keep_going: bool,
+    #[serde(default)]
+    pub outside_root: OutsideRoot,
+    pub matrix: Option<Matrix>,
+    #[serde(default)]
+    pub feature_combinations: FeatureCombinations,
+    pub powerset_depth: Option<usize>,
+    #[serde(default)]
+    pub path_prepend: Vec<PathBuf>,
+    #[serde(default)]
+    pub path_append: Vec<PathBuf>,
+    pub requires_env: Option<RequiresEnv>,
+    #[serde(default)]
+    pub secret_env: Vec<String>,
+    #[serde(default)]
+    pub params: IndexMap<String, Param>,
+    /// Removes the task from this Dorsfile, including when inherited from the workspace
+    #[serde(default)]
+    pub disabled: bool,
+    /// Whether members inherit this task from the workspace Dorsfile
+    #[serde(default = "default_inherit")]
+    pub inherit: bool,
+    /// The only members to inherit this task, by name or path
+    pub inherit_to: Option<HashSet<String>>,
+    /// How this task combines with a workspace task of the same name
+    #[serde(rename = "override", default)]
+    pub override_mode: Override,
+    /// Ran before the inherited command, when merging
+    pub command_prepend: Option<String>,
+    /// Ran after the inherited command, when merging
+    pub command_append: Option<String>,
+    /// Fields set in the Dorsfile, rather than left to their defaults
+    #[serde(skip)]
+    pub specified: HashSet<String>,
+    /// Declared in the user's own Dorsfile, rather than the project's
+    #[serde(skip)]
+    pub user: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Override {
+    /// Replace the workspace task entirely
+    #[default]
+    Replace,
+    /// Keep every field of the workspace task that isn't set here
+    Merge,
+}
+
+impl Task {
+    /// Catches `command-prepend` and `command-append` that were never merged onto an
+    /// inherited task, which would otherwise be ignored
+    pub fn check_merged(&self, task_name: &str) -> Result<(), DorsError> {
+        if self.command_prepend.is_some() || self.command_append.is_some() {
+            return Err(DorsError::NoTaskToMerge(task_name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// This task, with every field it doesn't set taken from `base`, and its
+    /// `command-prepend` and `command-append` placed around the resulting command
+    pub fn merge_onto(self, base: &Task) -> Task {
+        let mut merged = base.clone();
+        macro_rules! take_specified {
+            ($($key:literal => $field:ident),* $(,)?) => {
+                $(if self.specified.contains($key) {
+                    merged.$field = self.$field.clone();
+                })*
+            };
+        }
+        take_specified!(
+            "run-from" => run_from,
+            "relative-to" => relative_to,
+            "command" => command,
+            "before" => before,
+            "after" => after,
+            "keep-going" => keep_going,
+            "outside-root" => outside_root,
+            "matrix" => matrix,
+            "feature-combinations" => feature_combinations,
+            "powerset-depth" => powerset_depth,
+            "path-prepend" => path_prepend,
+            "path-append" => path_append,
+            "requires-env" => requires_env,
+            "secret-env" => secret_env,
+            "params" => params,
+            "disabled" => disabled,
+            "inherit" => inherit,
+            "inherit-to" => inherit_to,
+        );
+        if self.specified.contains("skip-members") || self.specified.contains("only-members") {
+            merged.member_modifiers = self.member_modifiers.clone();
+        }
+        merged.command = self
+            .command_prepend
+            .iter()
+            .chain(Some(&merged.command))
+            .chain(self.command_append.iter())
+            .filter(|command| !command.is_empty())
+            .map(|command| command.trim_end())
+            .collect::<Vec<_>>()
+            .join("\n");
+        merged.override_mode = self.override_mode;
+        merged.command_prepend = None;
+        merged.command_append = None;
+        merged.specified = self.specified;
+        merged.user = self.user;
+        merged
+    }
+}
+
+fn default_inherit() -> bool {
+    true
+}
+
+/// A task ran before or after another, either by name or with arguments of its own
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Dependency {
+    Name(String),
+    WithArgs(DependencyWithArgs),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "kebab-case")]
+pub struct DependencyWithArgs {
+    pub task: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Pass along the arguments given to the task that depends on this one
+    #[serde(default)]
+    pub forward_args: bool,
+}
+
+impl Dependency {
+    pub fn task(&self) -> &str {
+        match self {
+            Dependency::Name(task) => task,
+            Dependency::WithArgs(dependency) => &dependency.task,
+        }
+    }
+
+    /// Arguments to run the dependency with, given the arguments of the task depending on it
+    pub fn args(&self, parent_args: &[String]) -> Vec<String> {
+        match self {
+            Dependency::Name(_) => vec![],
+            Dependency::WithArgs(dependency) => {
+                let mut args = dependency.args.clone();
+                if dependency.forward_args {
+                    args.extend(parent_args.iter().cloned());
+                }
+                args
+            }
+        }
+    }
+}
+
+/// A named parameter, given on the command line as `--name value`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "kebab-case")]
+pub struct Param {
+    #[serde(rename = "type", default)]
+    pub kind: ParamType,
+    pub default: Option<Scalar>,
+    pub choices: Option<Vec<String>>,
+    pub help: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParamType {
+    #[default]
+    String,
+    Int,
+    Float,
+    Bool,
+}
+
+impl ParamType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParamType::String => "string",
+            ParamType::Int => "int",
+            ParamType::Float => "float",
+            ParamType::Bool => "bool",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ParamType::String => "a string",
+            ParamType::Int => "an integer",
+            ParamType::Float => "a number",
+            ParamType::Bool => "`true` or `false`",
+        }
+    }
+}
+
+/// Environment variables that must be set, optionally to one of a list of values
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum RequiresEnv {
+    Set(Vec<String>),
+    OneOf(IndexMap<String, Vec<String>>),
+}
+
+impl RequiresEnv {
+    pub fn requirements(&self) -> Vec<(&String, Option<&Vec<String>>)> {
+        match self {
+            RequiresEnv::Set(names) => names.iter().map(|name| (name, None)).collect(),
+            RequiresEnv::OneOf(names) => names
+                .iter()
+                .map(|(name, allowed)| (name, Some(allowed)))
+                .collect(),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -33,18 +278,75 @@ pub enum MemberModifiers {
     OnlyMembers(HashSet<String>),
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 #[serde(rename_all = "kebab-case")]
 pub enum Run {
+    #[default]
     Here,
     Path(PathBuf),
+    /// Every path listed, one after another
+    Paths(Vec<PathBuf>),
+    /// Every directory matching a pattern like `examples/*`
+    Glob(String),
     WorkspaceRoot,
     Members,
+    /// A single workspace member, by name
+    Member(String),
+    /// The root of every workspace listed in `[workspaces]`
+    Workspaces,
+    /// Every member of every workspace listed in `[workspaces]`
+    WorkspaceMembers,
 }
-impl Default for Run {
-    fn default() -> Run {
-        Run::Here
-    }
+
+/// What `run-from` paths are relative to
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RelativeTo {
+    /// The directory of the Dorsfile declaring the task, even once inherited
+    #[default]
+    Dorsfile,
+    /// The directory the task was invoked from
+    Invocation,
+}
+
+/// What to do when a `run-from = "members"` task is invoked from somewhere
+/// other than the workspace root
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutsideRoot {
+    #[default]
+    CurrentMember,
+    Error,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Matrix {
+    Builtin(BuiltinMatrix),
+    Axes(AxesMatrix),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuiltinMatrix {
+    Features,
+}
+
+/// User-defined axes, where every combination of values is ran except those excluded
+#[derive(Deserialize, Debug, Clone)]
+pub struct AxesMatrix {
+    #[serde(default)]
+    pub exclude: Vec<HashMap<String, String>>,
+    #[serde(flatten)]
+    pub axes: IndexMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeatureCombinations {
+    #[default]
+    Each,
+    Powerset,
 }
 
 impl Dorsfile {
@@ -59,13 +361,126 @@ impl Dorsfile {
                 .into())
             }
         };
-        Self::parse(file.as_str())
+        let mut dorsfile = Self::parse(file.as_str())?;
+        let dir = path
+            .as_ref()
+            .parent()
+            .unwrap()
+            .canonicalize()
+            .map_err(|e| DorsError::Unknown(e.into()))?;
+        dorsfile.resolve_paths(&dir);
+        dorsfile
+            .env
+            .iter_mut()
+            .for_each(|table| table.source = EnvSource::Dorsfile(path.as_ref().into()));
+        Ok(dorsfile)
     }
     pub fn parse(s: &str) -> Result<Dorsfile, Box<dyn Error>> {
-        Ok(toml::from_str(s).map_err(DorsError::CouldNotParseDorsfile)?)
+        let mut dorsfile: Dorsfile = toml::from_str(s).map_err(DorsError::CouldNotParseDorsfile)?;
+        dorsfile.validate_env()?;
+        if let Some((name, _)) = dorsfile.task.iter().find(|(_, task)| {
+            !matches!(task.override_mode, Override::Merge)
+                && (task.command_prepend.is_some() || task.command_append.is_some())
+        }) {
+            return Err(DorsError::WrapWithoutMerge(name.clone()).into());
+        }
+
+        // remember which fields each task sets, so that overrides can be merged
+        let raw: toml::Value = toml::from_str(s).map_err(DorsError::CouldNotParseDorsfile)?;
+        if let Some(tasks) = raw.get("task").and_then(toml::Value::as_table) {
+            for (name, fields) in tasks {
+                if let (Some(task), Some(fields)) = (dorsfile.task.get_mut(name), fields.as_table())
+                {
+                    task.specified = fields.keys().cloned().collect();
+                }
+            }
+        }
+        Ok(dorsfile)
+    }
+
+    /// PATH entries and `run-from` paths are relative to the Dorsfile that declares them,
+    /// so that they still point to the same place once inherited
+    fn resolve_paths(&mut self, dir: &Path) {
+        let resolve = |paths: &mut Vec<PathBuf>| {
+            paths.iter_mut().for_each(|path| {
+                // leave anything starting with a variable to be expanded by bash
+                if path.is_relative() && !path.to_str().unwrap().starts_with('$') {
+                    *path = normalize(&dir.join(&path));
+                }
+            })
+        };
+        resolve(&mut self.path_prepend);
+        resolve(&mut self.path_append);
+        self.extra_members
+            .values_mut()
+            .for_each(|path| *path = normalize(&dir.join(&path)));
+        if let Some(ref mut workspaces) = self.workspaces {
+            workspaces
+                .paths
+                .iter_mut()
+                .for_each(|path| *path = normalize(&dir.join(&path)));
+        }
+        self.task.values_mut().for_each(|task| {
+            resolve(&mut task.path_prepend);
+            resolve(&mut task.path_append);
+            if let RelativeTo::Invocation = task.relative_to {
+                return;
+            }
+            match task.run_from {
+                Run::Path(ref mut path) => *path = normalize(&dir.join(&path)),
+                Run::Paths(ref mut paths) => paths
+                    .iter_mut()
+                    .for_each(|path| *path = normalize(&dir.join(&path))),
+                Run::Glob(ref mut pattern) => {
+                    *pattern = normalize(&dir.join(&pattern)).to_str().unwrap().to_string()
+                }
+                _ => {}
+            }
+        });
+    }
+
+    /// Variables are exported in the order they are declared, so a variable
+    /// may only refer to those declared before it in the same `[[env]]` table
+    fn validate_env(&self) -> Result<(), DorsError> {
+        for table in &self.env {
+            for (index, (name, value)) in table.vars.iter().enumerate() {
+                let source = match value.evaluated_source() {
+                    Some(source) => source,
+                    None => continue,
+                };
+                if let Some(reference) = env_references(&source).into_iter().find(|reference| {
+                    table
+                        .vars
+                        .get_index_of(*reference)
+                        .is_some_and(|i| i > index)
+                }) {
+                    return Err(DorsError::EnvForwardReference {
+                        name: name.clone(),
+                        reference: reference.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
     }
 }
 
+/// Removes `.` and `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    use std::path::Component;
+    path.components()
+        .fold(PathBuf::new(), |mut normalized, component| {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                _ => normalized.push(component),
+            }
+            normalized
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,19 +505,130 @@ command = '''
 '''
 run-from = "members"
 skip-members = ["member1"]
+keep-going = true
 
 [task.skip]
 command = "echo hi"
 only-members = ["member2"]
+run-from = "members"
+outside-root = "error"
+
+[task.features]
+command = "cargo check --no-default-features --features \"$DORS_FEATURES\""
+run-from = "members"
+matrix = "features"
+feature-combinations = "powerset"
+powerset-depth = 2
+
+[task.cross]
+command = "cargo build --target $DORS_MATRIX_TARGET --profile $DORS_MATRIX_PROFILE"
+
+[task.cross.matrix]
+target = ["thumbv7em-none-eabihf", "x86_64-unknown-linux-gnu"]
+profile = ["dev", "release"]
+exclude = [{ target = "thumbv7em-none-eabihf", profile = "dev" }]
 
 [task.specific]
 command = "echo 'hi'"
 run-from = { path = "../whaat" }
 
+[task.deploy]
+command = "./deploy.sh"
+requires-env = { DEPLOY_ENV = ["staging", "prod"] }
+
+[task.publish]
+command = "npm publish"
+requires-env = ["NPM_TOKEN"]
+secret-env = ["NPM_TOKEN"]
+
+[task.build-server]
+before = ["codegen", { task = "build", args = ["--bin", "server"] }]
+after = [{ task = "notify", forward-args = true }]
+command = "echo built"
+
 [task.empty]
 "#;
         let mf = Dorsfile::parse(sample).unwrap();
-        assert_eq!(mf.task.len(), 6);
+        assert_eq!(mf.task.len(), 11);
         assert_eq!(mf.env.len(), 1);
     }
+
+    #[test]
+    fn test_merge_task() {
+        let workspace = Dorsfile::parse(
+            r#"
+[task.build]
+command = "cargo build"
+before = ["codegen"]
+keep-going = true
+path-prepend = ["tools"]
+"#,
+        )
+        .unwrap();
+        let member = Dorsfile::parse(
+            r#"
+[task.build]
+override = "merge"
+keep-going = false
+command-prepend = "echo start"
+command-append = """
+echo done
+"""
+"#,
+        )
+        .unwrap();
+        let merged = member.task["build"]
+            .clone()
+            .merge_onto(&workspace.task["build"]);
+        assert_eq!(merged.command, "echo start\ncargo build\necho done");
+        assert!(merged.check_merged("build").is_ok());
+        assert!(!merged.keep_going);
+        assert_eq!(merged.before.unwrap()[0].task(), "codegen");
+        assert_eq!(merged.path_prepend, [PathBuf::from("tools")]);
+
+        assert!(matches!(
+            Dorsfile::parse("[task.build]\ncommand-append = \"echo done\"")
+                .unwrap_err()
+                .kind(),
+            DorsError::WrapWithoutMerge(name) if name == "build"
+        ));
+        assert!(matches!(
+            member.task["build"].check_merged("build"),
+            Err(DorsError::NoTaskToMerge(name)) if name == "build"
+        ));
+    }
+
+    #[test]
+    fn test_env_order() {
+        let sample = r#"
+[[env]]
+Z = "1"
+A = { expand = "$Z/bin" }
+M = { shell = "${A}:$PATH" }
+B = "$C"
+C = "literal"
+"#;
+        let mf = Dorsfile::parse(sample).unwrap();
+        assert_eq!(
+            mf.env[0].vars.keys().collect::<Vec<_>>(),
+            ["Z", "A", "M", "B", "C"]
+        );
+
+        let sample = r#"
+[[env]]
+A = { expand = "${B}/bin" }
+B = "1"
+"#;
+        assert!(matches!(
+            Dorsfile::parse(sample).unwrap_err().kind(),
+            DorsError::EnvForwardReference { name, reference } if name == "A" && reference == "B"
+        ));
+    }
+
+    #[test]
+    fn test_reserved_task_names() {
+        // only the command line can't reach them, so they are still valid tasks
+        let dorsfile = Dorsfile::parse("[task.env]\ncommand = \"env\"").unwrap();
+        assert!(dorsfile.task.contains_key("env"));
+    }
 }
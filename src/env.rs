@@ -0,0 +1,397 @@
+use crate::error::DorsError;
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where a set of environment variables was defined
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvSource {
+    Builtin,
+    Dorsfile(PathBuf),
+    /// The `path-prepend` and `path-append` entries of every Dorsfile that applies
+    DorsfilePath,
+    Task(String),
+    EnvFile(PathBuf),
+    CommandLine,
+}
+
+impl Default for EnvSource {
+    fn default() -> EnvSource {
+        EnvSource::Dorsfile(PathBuf::new())
+    }
+}
+
+/// A set of environment variables, exported in the order they are declared
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnvTable {
+    #[serde(skip)]
+    pub source: EnvSource,
+    #[serde(flatten)]
+    pub vars: IndexMap<String, EnvValue>,
+}
+
+impl EnvTable {
+    pub fn new<I: IntoIterator<Item = (String, EnvValue)>>(source: EnvSource, vars: I) -> EnvTable {
+        EnvTable {
+            source,
+            vars: vars.into_iter().collect(),
+        }
+    }
+}
+
+/// The value of an environment variable. Plain values are exported literally,
+/// while `shell` and `expand` values are evaluated by bash.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EnvValue {
+    Literal(EnvParts),
+    Evaluated(EvaluatedEnv),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct EvaluatedEnv {
+    #[serde(flatten)]
+    pub evaluation: Evaluation,
+    #[serde(default = "default_separator")]
+    pub separator: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Evaluation {
+    /// Exported exactly as given
+    Literal(EnvParts),
+    /// Evaluated as bash, including command substitution
+    Shell(EnvParts),
+    /// Only `$VARIABLE` expansion is performed
+    Expand(EnvParts),
+}
+
+/// A single value, or a list of values to be joined by a separator
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EnvParts {
+    One(Scalar),
+    Many(Vec<Scalar>),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Scalar {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+fn default_separator() -> String {
+    ":".to_string()
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> EnvValue {
+        EnvValue::Literal(EnvParts::One(Scalar::String(value)))
+    }
+}
+
+impl From<&str> for EnvValue {
+    fn from(value: &str) -> EnvValue {
+        value.to_string().into()
+    }
+}
+
+impl Scalar {
+    pub fn to_text(&self) -> String {
+        match self {
+            Scalar::String(value) => value.clone(),
+            Scalar::Integer(value) => value.to_string(),
+            Scalar::Float(value) => value.to_string(),
+            Scalar::Boolean(value) => value.to_string(),
+        }
+    }
+}
+
+impl EnvParts {
+    fn to_texts(&self) -> Vec<String> {
+        match self {
+            EnvParts::One(value) => vec![value.to_text()],
+            EnvParts::Many(values) => values.iter().map(Scalar::to_text).collect(),
+        }
+    }
+}
+
+impl EnvValue {
+    /// Text that may be placed after `export NAME=` in a bash script
+    pub fn to_shell(&self) -> String {
+        match self {
+            EnvValue::Literal(parts) => quote_literal(&parts.to_texts().join(":")),
+            EnvValue::Evaluated(evaluated) => match evaluated.evaluation {
+                Evaluation::Literal(ref parts) => {
+                    quote_literal(&parts.to_texts().join(&evaluated.separator))
+                }
+                Evaluation::Shell(ref parts) => parts.to_texts().join(&evaluated.separator),
+                Evaluation::Expand(ref parts) => format!(
+                    "\"{}\"",
+                    parts
+                        .to_texts()
+                        .iter()
+                        .map(|part| escape_expand(part))
+                        .collect::<Vec<_>>()
+                        .join(&escape_quoted(&evaluated.separator))
+                ),
+            },
+        }
+    }
+
+    /// Source evaluated by bash, if any. Literal values cannot refer to other variables.
+    pub fn evaluated_source(&self) -> Option<String> {
+        match self {
+            EnvValue::Evaluated(EvaluatedEnv {
+                evaluation: Evaluation::Shell(parts),
+                ..
+            })
+            | EnvValue::Evaluated(EvaluatedEnv {
+                evaluation: Evaluation::Expand(parts),
+                ..
+            }) => Some(parts.to_texts().join(" ")),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `name` can be exported by bash, i.e. matches `[A-Za-z_][A-Za-z0-9_]*`
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Loads `KEY=VALUE` lines from a dotenv-style file. Values are taken literally,
+/// and blank lines, `#` comments, and leading `export`s are ignored.
+pub fn load_env_file(path: &Path) -> Result<EnvTable, DorsError> {
+    let file =
+        std::fs::read_to_string(path).map_err(|_| DorsError::CouldNotReadEnvFile(path.into()))?;
+    let vars = file
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| DorsError::InvalidEnvFile(path.into(), index + 1))?;
+            let key = key.trim();
+            if !is_valid_name(key) {
+                return Err(DorsError::InvalidEnvName(key.to_string()));
+            }
+            let value = value.trim();
+            let unquoted = ['"', '\'']
+                .iter()
+                .find(|quote| {
+                    value.len() > 1 && value.starts_with(**quote) && value.ends_with(**quote)
+                })
+                .map_or(value, |_| &value[1..value.len() - 1]);
+            Ok((key.to_string(), unquoted.into()))
+        })
+        .collect::<Result<Vec<_>, DorsError>>()?;
+    Ok(EnvTable::new(EnvSource::EnvFile(path.into()), vars))
+}
+
+/// Bash that exports every variable in `env`, in order
+pub fn export_script(env: &[EnvTable]) -> String {
+    env.iter()
+        .flat_map(|table| table.vars.iter())
+        .fold("".to_string(), |mut acc, (k, v)| {
+            acc.push_str(&format!("export {}={}\n", k, v.to_shell()));
+            acc
+        })
+}
+
+/// Evaluates every assignment in `env` from `workdir`, returning the value each
+/// variable held right after it was assigned, along with where it was defined
+pub fn evaluate(
+    env: &[EnvTable],
+    workdir: &Path,
+) -> Result<Vec<(String, String, EnvSource)>, DorsError> {
+    let assignments = env
+        .iter()
+        .flat_map(|table| table.vars.iter().map(move |var| (var, &table.source)))
+        .collect::<Vec<_>>();
+    let script = assignments
+        .iter()
+        .fold("".to_string(), |mut acc, ((k, v), _)| {
+            acc.push_str(&format!(
+                "export {}={}\nprintf '%s\\0' \"${}\"\n",
+                k,
+                v.to_shell(),
+                k
+            ));
+            acc
+        });
+    let stdout = run_script(&script, workdir)?;
+    Ok(assignments
+        .into_iter()
+        .zip(stdout.split('\0'))
+        .map(|(((name, _), source), value)| (name.clone(), value.to_string(), source.clone()))
+        .collect())
+}
+
+/// The values of `names` once `env` has been exported from `workdir`, or `None` if unset
+pub fn lookup(
+    env: &[EnvTable],
+    workdir: &Path,
+    names: &[&String],
+) -> Result<Vec<Option<String>>, DorsError> {
+    let mut script = export_script(env);
+    names.iter().for_each(|name| {
+        script.push_str(&format!(
+            "if [ -n \"${{{0}+set}}\" ]; then printf 'set%s\\0' \"${0}\"; else printf '\\0'; fi\n",
+            name
+        ))
+    });
+    let stdout = run_script(&script, workdir)?;
+    Ok(stdout
+        .split('\0')
+        .take(names.len())
+        .map(|value| value.strip_prefix("set").map(str::to_string))
+        .collect())
+}
+
+fn run_script(script: &str, workdir: &Path) -> Result<String, DorsError> {
+    let output = Command::new("bash")
+        .arg("-e")
+        .arg("-c")
+        .arg(script)
+        .current_dir(workdir)
+        .output()
+        .map_err(|e| DorsError::Unknown(e.into()))?;
+    if !output.status.success() {
+        return Err(DorsError::Unknown(
+            String::from_utf8_lossy(&output.stderr).into_owned().into(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A `PATH` value with entries placed around the inherited `$PATH`,
+/// keeping only the first occurrence of each entry
+pub fn path_value(prepend: &[PathBuf], append: &[PathBuf]) -> Option<EnvValue> {
+    if prepend.is_empty() && append.is_empty() {
+        return None;
+    }
+    let mut seen = vec![];
+    let mut entries = vec![];
+    let mut push = |entry: String| {
+        if !seen.contains(&entry) {
+            seen.push(entry.clone());
+            entries.push(Scalar::String(entry));
+        }
+    };
+    prepend
+        .iter()
+        .for_each(|path| push(path.to_str().unwrap().to_string()));
+    push("$PATH".to_string());
+    append
+        .iter()
+        .for_each(|path| push(path.to_str().unwrap().to_string()));
+    Some(EnvValue::Evaluated(EvaluatedEnv {
+        evaluation: Evaluation::Expand(EnvParts::Many(entries)),
+        separator: default_separator(),
+    }))
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Escapes text for use within double quotes, so that variables are
+/// expanded but commands are never executed
+fn escape_expand(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('`', "\\`")
+        .replace("$(", r"\$(")
+}
+
+/// Escapes text for use within double quotes, without any expansion
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('`', "\\`")
+        .replace('$', r"\$")
+}
+
+/// Names of the variables referenced by `$NAME` or `${NAME}` in a value
+pub fn env_references(value: &str) -> Vec<&str> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut references = vec![];
+    let mut rest = value;
+    while let Some(start) = rest.find('$') {
+        let escaped = rest[..start].ends_with('\\');
+        rest = &rest[start + 1..];
+        let name = rest.strip_prefix('{').unwrap_or(rest);
+        let end = name.find(|c| !is_name_char(c)).unwrap_or(name.len());
+        if !escaped && end > 0 && !name.starts_with(|c: char| c.is_ascii_digit()) {
+            references.push(&name[..end]);
+        }
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_names() {
+        assert!(is_valid_name("_PATH_2"));
+        assert!(!is_valid_name("2PATH"));
+        assert!(!is_valid_name("Y$(cmd)"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn test_env_values() {
+        let env: IndexMap<String, EnvValue> = toml::from_str(
+            r#"
+LITERAL = "it's $HOME; $(rm -rf /)"
+NUMBER = 3
+FLAG = true
+LIST = ["a b", "c"]
+SHELL = { shell = "$(echo hi)" }
+EXPAND = { expand = ["$HOME/bin", "$(oops)"], separator = ";" }
+SPACED = { literal = ["-a", "-b"], separator = " " }
+"#,
+        )
+        .unwrap();
+        let shell = |name: &str| env[name].to_shell();
+        assert_eq!(shell("LITERAL"), r"'it'\''s $HOME; $(rm -rf /)'");
+        assert_eq!(shell("NUMBER"), "'3'");
+        assert_eq!(shell("FLAG"), "'true'");
+        assert_eq!(shell("LIST"), "'a b:c'");
+        assert_eq!(shell("SHELL"), "$(echo hi)");
+        assert_eq!(shell("EXPAND"), r#""$HOME/bin;\$(oops)""#);
+        assert_eq!(shell("SPACED"), "'-a -b'");
+
+        assert_eq!(env["LITERAL"].evaluated_source(), None);
+
+        let path = |path: &str| PathBuf::from(path);
+        assert_eq!(
+            path_value(&[path("/a"), path("/b")], &[path("/a"), path("/c")])
+                .unwrap()
+                .to_shell(),
+            r#""/a:/b:$PATH:/c""#
+        );
+        assert_eq!(path_value(&[], &[]), None);
+        assert_eq!(
+            env_references(r"$A ${B}/\$C $1 $(pwd) ${D_2}"),
+            ["A", "B", "D_2"]
+        );
+    }
+}
@@ -1,15 +1,80 @@
 use std::fmt;
+use std::path::PathBuf;
 
 // All gracefully handled errors
 #[derive(Debug)]
 pub enum DorsError {
     CouldNotParseDorsfile(toml::de::Error),
+    EnvForwardReference {
+        name: String,
+        reference: String,
+    },
+    CouldNotReadEnvFile(PathBuf),
+    InvalidEnvFile(PathBuf, usize),
+    InvalidEnvName(String),
     NoDorsfile,
     NoMemberDorsfile,
     NoTask(String),
+    WrapWithoutMerge(String),
+    NoTaskToMerge(String),
+    NoMembersMatched(String),
+    MembersOutsideWorkspaceRoot(String),
+    NoRunPaths(String),
+    MissingRunPath {
+        task: String,
+        path: PathBuf,
+    },
+    NotInMember(PathBuf),
+    MissingExtraMember(String, PathBuf),
+    UnknownMatrixAxis {
+        task: String,
+        axis: String,
+    },
+    EmptyMatrix(String),
+    UnknownMember {
+        task: String,
+        member: String,
+        suggestion: Option<String>,
+    },
+    UnmetEnvRequirements(Vec<UnmetEnvRequirement>),
+    InvalidTaskArgs {
+        task: String,
+        message: String,
+        usage: String,
+    },
     Unknown(Box<dyn std::error::Error>),
 }
 
+/// An environment variable that a task requires, but is unset or has an unexpected value
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmetEnvRequirement {
+    pub task: String,
+    pub name: String,
+    pub found: Option<String>,
+    pub allowed: Option<Vec<String>>,
+}
+
+impl fmt::Display for UnmetEnvRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Task `{}` requires `{}`", self.task, self.name)?;
+        if let Some(ref allowed) = self.allowed {
+            write!(
+                f,
+                " to be one of {}",
+                allowed
+                    .iter()
+                    .map(|value| format!("`{}`", value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        match self.found {
+            Some(ref found) => write!(f, ", but found `{}`", found),
+            None => write!(f, ", but it is not set"),
+        }
+    }
+}
+
 pub trait Error: std::error::Error {
     fn kind(&self) -> &DorsError;
 }
@@ -33,6 +98,25 @@ impl fmt::Display for DorsError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.kind() {
             DorsError::CouldNotParseDorsfile(e) => write!(f, "Could not parse dorsfile: {}", e),
+            DorsError::EnvForwardReference { name, reference } => write!(
+                f,
+                "Environment variable `{}` refers to `{}`, which is declared after it",
+                name, reference
+            ),
+            DorsError::CouldNotReadEnvFile(path) => {
+                write!(f, "Could not read env file `{}`", path.to_str().unwrap())
+            }
+            DorsError::InvalidEnvFile(path, line) => write!(
+                f,
+                "Expected `KEY=VALUE` on line {} of env file `{}`",
+                line,
+                path.to_str().unwrap()
+            ),
+            DorsError::InvalidEnvName(name) => write!(
+                f,
+                "`{}` is not a valid environment variable name. Use only letters, digits, and `_`, not starting with a digit",
+                name
+            ),
             DorsError::NoDorsfile => {
                 // TODO offer to create one
                 write!(f, "Expected `Dorsfile.toml`")
@@ -42,6 +126,80 @@ impl fmt::Display for DorsError {
                 "Need `Dorsfile.toml` at either member or workspace root."
             ),
             DorsError::NoTask(task) => write!(f, "No task named: `{}`", task),
+            DorsError::WrapWithoutMerge(task) => write!(
+                f,
+                "Task `{}` sets `command-prepend` or `command-append`, which need `override = \"merge\"`",
+                task
+            ),
+            DorsError::NoTaskToMerge(task) => write!(
+                f,
+                "Task `{}` wraps the command of an inherited task, but there is no task to inherit",
+                task
+            ),
+            DorsError::NoMembersMatched(task) => {
+                write!(f, "No workspace members matched task `{}`", task)
+            }
+            DorsError::MembersOutsideWorkspaceRoot(task) => write!(
+                f,
+                "Task `{}` runs from members, and can only be ran from the workspace root",
+                task
+            ),
+            DorsError::NoRunPaths(task) => {
+                write!(f, "Task `{}` has no directories to run from", task)
+            }
+            DorsError::MissingRunPath { task, path } => write!(
+                f,
+                "Task `{}` runs from `{}`, which is not a directory",
+                task,
+                path.to_str().unwrap()
+            ),
+            DorsError::MissingExtraMember(name, path) => write!(
+                f,
+                "Extra member `{}` is at `{}`, which is not a directory",
+                name,
+                path.to_str().unwrap()
+            ),
+            DorsError::NotInMember(path) => write!(
+                f,
+                "`{}` is not inside of a workspace member",
+                path.to_str().unwrap()
+            ),
+            DorsError::UnknownMatrixAxis { task, axis } => write!(
+                f,
+                "Task `{}` excludes matrix axis `{}`, which is not defined",
+                task, axis
+            ),
+            DorsError::EmptyMatrix(task) => {
+                write!(
+                    f,
+                    "Every cell of the matrix for task `{}` is excluded",
+                    task
+                )
+            }
+            DorsError::UnknownMember {
+                task,
+                member,
+                suggestion,
+            } => {
+                write!(
+                    f,
+                    "Task `{}` refers to `{}`, which is not a workspace member",
+                    task, member
+                )?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ". Did you mean `{}`?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            DorsError::UnmetEnvRequirements(unmet) => {
+                write!(f, "Missing or invalid environment variables:")?;
+                unmet
+                    .iter()
+                    .try_for_each(|requirement| write!(f, "\n  {}", requirement))
+            }
+            DorsError::InvalidTaskArgs { message, usage, .. } => {
+                write!(f, "{}\n\nUsage: {}", message, usage)
+            }
             DorsError::Unknown(e) => write!(f, "Error: {}", e),
         }
     }
@@ -0,0 +1,102 @@
+use std::path::{Component, Path, PathBuf};
+
+/// Directories under `dir` matching `pattern`, where `*` and `?` match within a single
+/// path component. Results are sorted, and hidden directories are only matched explicitly.
+pub fn glob_dirs(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut matches = vec![dir.to_path_buf()];
+    for component in Path::new(pattern).components() {
+        matches = match component {
+            Component::Normal(part) => {
+                let part = part.to_str().unwrap();
+                if !part.contains(['*', '?']) {
+                    matches.into_iter().map(|path| path.join(part)).collect()
+                } else {
+                    matches
+                        .into_iter()
+                        .flat_map(|path| children(&path))
+                        .filter(|path| {
+                            let name = path.file_name().unwrap().to_str().unwrap();
+                            (part.starts_with('.') || !name.starts_with('.'))
+                                && wildcard_match(part, name)
+                        })
+                        .collect()
+                }
+            }
+            other => matches
+                .into_iter()
+                .map(|path| path.join(other.as_os_str()))
+                .collect(),
+        };
+    }
+    matches.retain(|path| path.is_dir());
+    matches.sort();
+    matches
+}
+
+fn children(dir: &Path) -> Vec<PathBuf> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters and `?` any one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // where to resume from if the most recent `*` needs to match more characters
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("member?", "member1"));
+        assert!(wildcard_match("m*r*", "member2"));
+        assert!(wildcard_match("*-example", "hello-example"));
+        assert!(!wildcard_match("member?", "member10"));
+        assert!(!wildcard_match("*-example", "example"));
+    }
+
+    #[test]
+    fn test_glob_dirs() {
+        let dir = Path::new("tests/workspace_all");
+        assert_eq!(
+            glob_dirs(dir, "member*"),
+            [dir.join("member1"), dir.join("member2")]
+        );
+        assert_eq!(glob_dirs(dir, "*1/src"), [dir.join("member1/src")]);
+        assert_eq!(glob_dirs(dir, "member1/Cargo.*"), Vec::<PathBuf>::new());
+        assert!(glob_dirs(dir, "nothing-*").is_empty());
+    }
+}
@@ -1,123 +1,277 @@
 #![deny(clippy::print_stdout)]
 mod dorsfile;
+mod env;
 mod error;
+mod glob;
+mod mask;
+mod matrix;
+mod params;
+mod summary;
 mod take_while_ext;
 
-pub use crate::error::{DorsError, Error};
+pub use crate::error::{DorsError, Error, UnmetEnvRequirement};
 
 use cargo_metadata::MetadataCommand;
 use colored::Colorize;
-use dorsfile::{Dorsfile, MemberModifiers, Run};
-use std::collections::{HashMap, HashSet};
+use dorsfile::{
+    BuiltinMatrix, Dorsfile, Matrix, MemberModifiers, OutsideRoot, Override, Run, Task,
+};
+use env::{EnvSource, EnvTable, EnvValue};
+use indexmap::IndexMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, BufReader, IsTerminal};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::process::ExitStatus;
+use std::process::{ExitStatus, Stdio};
+use std::time::Instant;
+use summary::Summary;
 use take_while_ext::TakeWhileLastExt;
 
 #[derive(Debug)]
 struct DorsfileGetter {
-    workspace_root: PathBuf,
+    workspace: CargoWorkspaceInfo,
+    /// The workspace Dorsfile, extended from the user's Dorsfile and any above the
+    /// workspace root. Without a Dorsfile of its own, the workspace takes theirs.
     workspace_dorsfile: Option<Dorsfile>,
 }
 impl DorsfileGetter {
-    pub fn new<P: AsRef<Path>>(workspace_root: P) -> Result<DorsfileGetter, Box<dyn Error>> {
-        let workspace_dorsfile_path = workspace_root.as_ref().join("./Dorsfile.toml");
+    /// Loads the workspace Dorsfile, adding any extra members it declares to `workspace`
+    pub fn new(
+        workspace: &mut CargoWorkspaceInfo,
+        options: &RunOptions,
+    ) -> Result<DorsfileGetter, Box<dyn Error>> {
+        let workspace_dorsfile_path = workspace.root.join("./Dorsfile.toml");
+        let workspace_dorsfile = if workspace_dorsfile_path.exists() {
+            Some(Dorsfile::load(&workspace_dorsfile_path)?)
+        } else {
+            None
+        };
+        if let Some(ref workspace_dorsfile) = workspace_dorsfile {
+            workspace.add_extra_members(&workspace_dorsfile.extra_members)?;
+        }
+
+        let mut layers = vec![];
+        // the user's own Dorsfile is the lowest layer of all
+        match user_dorsfile_path() {
+            Some(path) if options.user_dorsfile && path.exists() => {
+                let mut dorsfile = Dorsfile::load(path)?;
+                dorsfile.task.values_mut().for_each(|task| task.user = true);
+                layers.push(dorsfile);
+            }
+            _ => {}
+        }
+        if !workspace_dorsfile
+            .as_ref()
+            .is_some_and(|dorsfile| dorsfile.root)
+        {
+            layers.append(&mut repository_dorsfiles(&workspace.root)?);
+        }
+        let mut repository_dorsfile: Option<Dorsfile> = None;
+        for layer in layers {
+            repository_dorsfile = Some(match repository_dorsfile {
+                Some(mut dorsfile) => {
+                    apply_layer(&mut dorsfile, layer, None, &mut HashSet::new());
+                    dorsfile
+                }
+                None => layer,
+            });
+        }
+        if let Some(ref mut repository_dorsfile) = repository_dorsfile {
+            // Tasks ran on every workspace are ran from the root of each one, so
+            // clear anything that would have them ran again
+            repository_dorsfile.workspaces = None;
+            repository_dorsfile.extra_members.clear();
+            repository_dorsfile.task.values_mut().for_each(|task| {
+                let run_from = match task.run_from {
+                    Run::Workspaces => Run::Here,
+                    Run::WorkspaceMembers => Run::Members,
+                    _ => return,
+                };
+                task.run_from = run_from;
+                task.before = None;
+                task.after = None;
+            });
+        }
+        let workspace_dorsfile = match (workspace_dorsfile, repository_dorsfile) {
+            (Some(workspace_dorsfile), Some(mut dorsfile)) => {
+                dorsfile.extra_members = workspace_dorsfile.extra_members.clone();
+                dorsfile.workspaces = workspace_dorsfile.workspaces.clone();
+                apply_layer(&mut dorsfile, workspace_dorsfile, None, &mut HashSet::new());
+                Some(dorsfile)
+            }
+            (None, repository_dorsfile) => repository_dorsfile,
+            (workspace_dorsfile, None) => workspace_dorsfile,
+        };
         Ok(DorsfileGetter {
-            workspace_root: workspace_root.as_ref().into(),
-            workspace_dorsfile: if workspace_dorsfile_path.exists() {
-                Some(Dorsfile::load(&workspace_dorsfile_path)?)
-            } else {
-                None
-            },
+            workspace: workspace.clone(),
+            workspace_dorsfile,
         })
     }
 
+    /// Workspace tasks as seen from `crate_path`, leaving out those not inherited there
+    fn inherited_tasks(&self, crate_path: &Path) -> Result<HashMap<String, Task>, DorsError> {
+        let member = self.workspace.member_containing(crate_path).ok();
+        let mut tasks = HashMap::new();
+        for (name, task) in &self.workspace_dorsfile.as_ref().unwrap().task {
+            let inherited = match task.inherit_to {
+                Some(ref inherit_to) => {
+                    self.workspace.check_member_filter(name, inherit_to)?;
+                    member.is_some_and(|member| {
+                        self.workspace.filter_matches(
+                            inherit_to,
+                            member,
+                            &self.workspace.members[member],
+                        )
+                    })
+                }
+                None => task.inherit,
+            };
+            if !inherited {
+                continue;
+            }
+            let mut task = task.clone();
+            // Clear all befores and afters from member task
+            // so that they are not ran on both member and workspace root
+            task.before = None;
+            task.after = None;
+
+            // Clear any 'run-from = "member"' from the workspace, as we ARE running
+            // from the member
+            if let Run::Members = task.run_from {
+                task.run_from = Run::Here;
+            }
+            tasks.insert(name.clone(), task);
+        }
+        Ok(tasks)
+    }
+
+    /// Dorsfiles between the workspace root and `crate_path`, outermost first.
+    /// Paths outside of the workspace only have their own Dorsfile.
+    fn nested_dorsfile_paths(&self, crate_path: &Path) -> Vec<PathBuf> {
+        let crate_path = crate_path.canonicalize().unwrap();
+        let root = self.workspace.root.canonicalize().unwrap();
+        let mut paths = crate_path
+            .ancestors()
+            .take_while(|dir| *dir != root && (crate_path.starts_with(&root) || *dir == crate_path))
+            .map(|dir| dir.join("Dorsfile.toml"))
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+        paths.reverse();
+        paths
+    }
+
     pub fn get<P: AsRef<Path>>(&self, crate_path: P) -> Result<Dorsfile, Box<dyn Error>> {
         let crate_path = crate_path.as_ref();
-        if crate_path.canonicalize().unwrap() == self.workspace_root.canonicalize().unwrap() {
-            return Ok(self
+        if crate_path.canonicalize().unwrap() == self.workspace.root.canonicalize().unwrap() {
+            let mut dorsfile = self
                 .workspace_dorsfile
                 .as_ref()
                 .cloned()
-                .ok_or(DorsError::NoDorsfile)?);
-        }
-        let local = crate_path.join("./Dorsfile.toml");
-
-        let mut dorsfile = match (local.exists(), self.workspace_dorsfile.is_some()) {
-            (true, true) => {
-                // Extend local dorsfile with workspace dorsfile
-                let mut curr = Dorsfile::load(local)?;
-                let workspace_dorsfile = self.workspace_dorsfile.as_ref().unwrap();
-                let mut env = workspace_dorsfile.env.clone();
-                let mut task = workspace_dorsfile.task.clone();
-
-                task.values_mut().for_each(|task| {
-                    // Clear all befores and afters from member task
-                    // so that they are not ran on both member and workspace root
-                    task.before = None;
-                    task.after = None;
-
-                    // Clear any 'run-from = "member"' from the workspace, as we ARE running
-                    // from the member
-                    if let Run::Members = task.run_from {
-                        task.run_from = Run::Here;
-                    }
-                });
-
-                env.extend(curr.env.drain(..));
-                task.extend(curr.task.drain());
-                curr.env = env;
-                curr.task = task;
-                curr
-            }
-            (true, false) => Dorsfile::load(local)?,
-            (false, true) => {
-                let mut curr = self.workspace_dorsfile.as_ref().cloned().unwrap();
-
-                let mut task = curr.task.clone();
-                task.values_mut().for_each(|task| {
-                    // Clear all befores and afters from member task
-                    // so that they are not ran on both member and workspace root
-                    task.before = None;
-                    task.after = None;
-
-                    // Clear any 'run-from = "member"' from the workspace, as we ARE running
-                    // from the member
-                    if let Run::Members = task.run_from {
-                        task.run_from = Run::Here;
-                    }
-                });
+                .ok_or(DorsError::NoDorsfile)?;
+            dorsfile.task.retain(|_, task| !task.disabled);
+            for (name, task) in &dorsfile.task {
+                task.check_merged(name)?;
+            }
+            return Ok(dorsfile);
+        }
+        let layers = self.nested_dorsfile_paths(crate_path);
 
-                curr.task = task;
-                curr
+        let (mut dorsfile, mut from_workspace) = match self.workspace_dorsfile {
+            Some(ref workspace_dorsfile) => {
+                let mut curr = workspace_dorsfile.clone();
+                curr.task = self.inherited_tasks(crate_path)?;
+                let from_workspace = curr.task.keys().cloned().collect::<HashSet<_>>();
+                (curr, from_workspace)
             }
-            (false, false) => return Err(DorsError::NoMemberDorsfile.into()),
+            None if layers.is_empty() => return Err(DorsError::NoMemberDorsfile.into()),
+            None => (Dorsfile::default(), HashSet::new()),
         };
+        // Each nested Dorsfile extends the ones above it, the closest taking precedence
+        for layer in layers {
+            apply_layer(
+                &mut dorsfile,
+                Dorsfile::load(layer)?,
+                self.workspace_dorsfile.as_ref(),
+                &mut from_workspace,
+            );
+        }
+        // a member may disable a task, whether its own or inherited
+        dorsfile.task.retain(|_, task| !task.disabled);
+        for (name, task) in &dorsfile.task {
+            task.check_merged(name)?;
+        }
 
         // extend environment
-        let builtins: HashMap<_, _> = [(
+        let mut builtins: IndexMap<_, _> = [(
             "CARGO_WORKSPACE_ROOT",
-            self.workspace_root.to_str().unwrap(),
+            self.workspace.root.to_str().unwrap().to_string(),
         )]
         .iter()
         .cloned()
-        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .map(|(key, value)| (key.to_string(), value.into()))
         .collect();
-        let mut env = vec![builtins];
-        env.extend(dorsfile.env.drain(..));
+        if let Ok(name) = self.workspace.member_containing(crate_path) {
+            let member = &self.workspace.members[name];
+            builtins.insert("DORS_MEMBER_NAME".to_string(), name.as_str().into());
+            // extra members aren't crates, so there is nothing more to tell
+            if !member.extra {
+                builtins.extend(
+                    vec![
+                        ("DORS_MEMBER_VERSION", member.version.clone()),
+                        (
+                            "DORS_MEMBER_MANIFEST",
+                            member.manifest_path.to_str().unwrap().to_string(),
+                        ),
+                        ("DORS_MEMBER_FEATURES", member.features.join(",")),
+                    ]
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.into())),
+                );
+            }
+        }
+        let mut env = vec![EnvTable::new(EnvSource::Builtin, builtins)];
+        env.append(&mut dorsfile.env);
         dorsfile.env = env;
         Ok(dorsfile)
     }
 }
 
+#[derive(Debug, Clone)]
+struct Member {
+    path: PathBuf,
+    version: String,
+    manifest_path: PathBuf,
+    features: Vec<String>,
+    /// Declared in `[extra-members]` rather than known to cargo
+    extra: bool,
+}
+
+#[derive(Debug, Clone)]
 struct CargoWorkspaceInfo {
-    members: HashMap<String, PathBuf>,
+    members: BTreeMap<String, Member>,
     root: PathBuf,
 }
 
 impl CargoWorkspaceInfo {
     fn new(dir: &Path) -> CargoWorkspaceInfo {
-        let metadata = MetadataCommand::new().current_dir(&dir).exec().unwrap();
+        let lists_workspaces = Dorsfile::load(dir.join("Dorsfile.toml"))
+            .is_ok_and(|dorsfile| dorsfile.workspaces.is_some());
+        let metadata = match MetadataCommand::new().current_dir(dir).exec() {
+            Ok(metadata)
+                if !lists_workspaces || metadata.workspace_root == dir.canonicalize().unwrap() =>
+            {
+                metadata
+            }
+            // a Dorsfile listing workspaces is a root of its own, whether or not cargo knows of it
+            _ if lists_workspaces => {
+                return CargoWorkspaceInfo {
+                    members: BTreeMap::new(),
+                    root: dir.canonicalize().unwrap(),
+                }
+            }
+            result => result.unwrap(),
+        };
         let root = metadata.workspace_root;
         // allow O(1) referencing of package information
         let packages: HashMap<_, _> = metadata
@@ -130,20 +284,236 @@ impl CargoWorkspaceInfo {
             .into_iter()
             .map(|member| {
                 let package = packages[&member];
+                let mut features = package.features.keys().cloned().collect::<Vec<_>>();
+                features.sort();
                 (
                     package.name.clone(),
-                    package.manifest_path.parent().unwrap().into(),
+                    Member {
+                        path: package.manifest_path.parent().unwrap().into(),
+                        version: package.version.to_string(),
+                        manifest_path: package.manifest_path.clone(),
+                        features,
+                        extra: false,
+                    },
                 )
             })
             .collect();
         CargoWorkspaceInfo { members, root }
     }
+
+    /// Adds directories outside of cargo's knowledge as members. Cargo members
+    /// take precedence over extra members of the same name.
+    fn add_extra_members(&mut self, extra: &BTreeMap<String, PathBuf>) -> Result<(), DorsError> {
+        for (name, path) in extra {
+            if !path.is_dir() {
+                return Err(DorsError::MissingExtraMember(name.clone(), path.clone()));
+            }
+            self.members.entry(name.clone()).or_insert_with(|| Member {
+                path: path.clone(),
+                version: String::new(),
+                manifest_path: PathBuf::new(),
+                features: vec![],
+                extra: true,
+            });
+        }
+        Ok(())
+    }
+
+    /// Name of the member that contains `dir`
+    fn member_containing(&self, dir: &Path) -> Result<&String, DorsError> {
+        let dir = dir.canonicalize().unwrap();
+        self.members
+            .iter()
+            .filter(|(_, member)| dir.starts_with(member.path.canonicalize().unwrap()))
+            .max_by_key(|(_, member)| member.path.components().count())
+            .map(|(name, _)| name)
+            .ok_or(DorsError::NotInMember(dir))
+    }
+
+    /// Path of a member relative to the workspace root
+    fn short_path<'a>(&self, path: &'a Path) -> &'a Path {
+        if path.is_relative() {
+            path
+        } else {
+            path.strip_prefix(&self.root).unwrap()
+        }
+    }
+
+    /// Members that a task should be ran on, after applying skip-members or only-members.
+    /// Filter entries may be either a member's name or its path relative to the root.
+    fn select_members(
+        &self,
+        task_name: &str,
+        modifiers: &Option<MemberModifiers>,
+    ) -> Result<Vec<(&String, &Member)>, DorsError> {
+        if let Some(MemberModifiers::SkipMembers(filter))
+        | Some(MemberModifiers::OnlyMembers(filter)) = modifiers
+        {
+            self.check_member_filter(task_name, filter)?;
+        }
+
+        let members = self
+            .members
+            .iter()
+            .filter(|(name, member)| match modifiers {
+                Some(MemberModifiers::SkipMembers(skips)) => {
+                    !self.filter_matches(skips, name, member)
+                }
+                Some(MemberModifiers::OnlyMembers(onlys)) => {
+                    self.filter_matches(onlys, name, member)
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            return Err(DorsError::NoMembersMatched(task_name.to_string()));
+        }
+        Ok(members)
+    }
+
+    /// Where a qualified task reference, like `member:task` or `//:task` for the workspace
+    /// root, is defined. Anything else, including names like `test:unit` that don't start
+    /// with a member, is left to the referring Dorsfile.
+    fn locate_task<'a>(&self, reference: &'a str) -> Option<(&'a str, PathBuf)> {
+        match reference.split_once(':') {
+            Some(("//", task)) => Some((task, self.root.clone())),
+            Some((member, task)) => self
+                .members
+                .get(member)
+                .map(|member| (task, member.path.clone())),
+            None => None,
+        }
+    }
+
+    /// Whether a member is listed in a filter, by either its name or its path
+    fn filter_matches(&self, filter: &HashSet<String>, name: &str, member: &Member) -> bool {
+        filter.contains(name)
+            || filter.contains(&self.short_path(&member.path).to_str().unwrap().to_string())
+    }
+
+    /// Catches typos in a filter, rather than silently ignoring them
+    fn check_member_filter(
+        &self,
+        task_name: &str,
+        filter: &HashSet<String>,
+    ) -> Result<(), DorsError> {
+        let mut filter_entries = filter.iter().collect::<Vec<_>>();
+        filter_entries.sort();
+        match filter_entries.into_iter().find(|entry| {
+            !self.members.iter().any(|(name, member)| {
+                *entry == name || *entry == self.short_path(&member.path).to_str().unwrap()
+            })
+        }) {
+            Some(unknown) => Err(DorsError::UnknownMember {
+                task: task_name.to_string(),
+                member: unknown.clone(),
+                suggestion: self.suggest_member(unknown),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The member name or path most similar to `unknown`, if any are close enough
+    fn suggest_member(&self, unknown: &str) -> Option<String> {
+        self.members
+            .iter()
+            .flat_map(|(name, member)| {
+                vec![
+                    name.clone(),
+                    self.short_path(&member.path).to_str().unwrap().to_string(),
+                ]
+            })
+            .map(|candidate| (strsim::jaro_winkler(unknown, &candidate), candidate))
+            .filter(|(similarity, _)| *similarity > 0.8)
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+            .map(|(_, candidate)| candidate)
+    }
+}
+
+/// The user's own Dorsfile, kept in `$XDG_CONFIG_HOME/dors`, or `~/.config/dors` by default
+fn user_dorsfile_path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("dors/Dorsfile.toml"))
+}
+
+/// Dorsfiles above the workspace root, outermost first. The search stops at the
+/// root of the git repository, or at a Dorsfile marked `root = true`.
+fn repository_dorsfiles(workspace_root: &Path) -> Result<Vec<Dorsfile>, Box<dyn Error>> {
+    let mut dorsfiles = vec![];
+    let workspace_root = workspace_root.canonicalize().unwrap();
+    if workspace_root.join(".git").exists() {
+        return Ok(dorsfiles);
+    }
+    for dir in workspace_root.ancestors().skip(1) {
+        let path = dir.join("Dorsfile.toml");
+        if path.exists() {
+            let dorsfile = Dorsfile::load(&path)?;
+            let is_root = dorsfile.root;
+            dorsfiles.push(dorsfile);
+            if is_root {
+                break;
+            }
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    dorsfiles.reverse();
+    Ok(dorsfiles)
 }
 
+/// Extends `dorsfile` with a more specific `layer`, whose tasks and environment take
+/// precedence. `cleared` names the tasks inherited from `workspace_dorsfile` with their
+/// befores and afters cleared.
+fn apply_layer(
+    dorsfile: &mut Dorsfile,
+    mut layer: Dorsfile,
+    workspace_dorsfile: Option<&Dorsfile>,
+    cleared: &mut HashSet<String>,
+) {
+    dorsfile.env.append(&mut layer.env);
+    for (name, layer_task) in layer.task.drain() {
+        let layer_task = match (&layer_task.override_mode, dorsfile.task.remove(&name)) {
+            (Override::Merge, Some(mut inherited)) => {
+                // befores and afters are only cleared to avoid running them on
+                // both the workspace and its members, so a merge keeps them
+                if cleared.contains(&name) {
+                    let workspace_task = &workspace_dorsfile.unwrap().task[&name];
+                    inherited.before = workspace_task.before.clone();
+                    inherited.after = workspace_task.after.clone();
+                }
+                layer_task.merge_onto(&inherited)
+            }
+            _ => layer_task,
+        };
+        cleared.remove(&name);
+        dorsfile.task.insert(name, layer_task);
+    }
+
+    // Layered entries are more specific, so they take precedence in PATH
+    layer.path_prepend.append(&mut dorsfile.path_prepend);
+    dorsfile.path_prepend = layer.path_prepend;
+    dorsfile.path_append.append(&mut layer.path_append);
+    dorsfile.secret_env.append(&mut layer.secret_env);
+}
+
+/// Names of the variables to mask in a task's output
+fn secret_env(task: &Task, dorsfile: &Dorsfile) -> Vec<String> {
+    let mut secret_env = dorsfile.secret_env.clone();
+    secret_env.extend(task.secret_env.iter().cloned());
+    secret_env
+}
+
+/// Runs `command` with `env` exported. When there are secrets to mask, output is piped
+/// through dors rather than inherited.
 fn run_command(
     command: &str,
     workdir: &Path,
-    env: &[HashMap<String, String>],
+    env: &[EnvTable],
+    secret_env: &[String],
     args: &[String],
 ) -> ExitStatus {
     use rand::distributions::Alphanumeric;
@@ -158,38 +528,210 @@ fn run_command(
         .canonicalize()
         .unwrap()
         .join(format!("tmp-{}.sh", chars));
-    let mut script = env
+    let mut script = env::export_script(env);
+    // the values to mask are those the command itself sees, so they are written
+    // ahead of its output rather than evaluated separately
+    secret_env
         .iter()
-        .flatten()
-        .fold("".to_string(), |mut acc, (k, v)| {
-            acc.push_str(&format!("export {}={}\n", k, v));
-            acc
-        });
+        .for_each(|name| script.push_str(&format!("printf '%s\\0' \"${{{}-}}\"\n", name)));
     script.push_str(command);
-    script.push_str("\n");
+    script.push('\n');
     std::fs::write(&file, &script).unwrap();
-    let exit_status = Command::new("bash")
+    let mut command = Command::new("bash");
+    command
         .arg("-e")
         .arg(file.to_str().unwrap())
         .args(args)
-        .current_dir(workdir)
-        .spawn()
-        .unwrap()
-        .wait()
-        .unwrap();
+        .current_dir(workdir);
+    let exit_status = if secret_env.is_empty() {
+        command.spawn().unwrap().wait().unwrap()
+    } else {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let stderr = child.stderr.take().unwrap();
+        let secrets = mask::secret_patterns(secret_env.iter().map(|_| {
+            let mut value = vec![];
+            stdout.read_until(b'\0', &mut value).unwrap_or(0);
+            value.pop();
+            String::from_utf8_lossy(&value).into_owned()
+        }));
+        std::thread::scope(|scope| {
+            scope.spawn(|| mask::copy_masked(stdout, std::io::stdout(), &secrets));
+            scope.spawn(|| mask::copy_masked(stderr, std::io::stderr(), &secrets));
+        });
+        child.wait().unwrap()
+    };
     std::fs::remove_file(file).unwrap();
     exit_status
 }
 
+/// Every environment variable a task is ran with, from lowest to highest precedence
+fn task_env(
+    task_name: &str,
+    task: &Task,
+    dorsfile: &Dorsfile,
+    overrides: &[EnvTable],
+) -> Vec<EnvTable> {
+    let mut env = vec![EnvTable::new(
+        EnvSource::Builtin,
+        vec![("DORS_TASK".to_string(), task_name.into())],
+    )];
+    env.extend(dorsfile.env.iter().cloned());
+    env.extend(dorsfile_path_env(dorsfile));
+    // the task's own entries go around those of its Dorsfiles
+    if let Some(path) = env::path_value(&task.path_prepend, &task.path_append) {
+        env.push(EnvTable::new(
+            EnvSource::Task(task_name.to_string()),
+            vec![("PATH".to_string(), path)],
+        ));
+    }
+    env.extend(overrides.iter().cloned());
+    env
+}
+
+/// The `PATH` set by a Dorsfile's own `path-prepend` and `path-append`, if any
+fn dorsfile_path_env(dorsfile: &Dorsfile) -> Option<EnvTable> {
+    env::path_value(&dorsfile.path_prepend, &dorsfile.path_append)
+        .map(|path| EnvTable::new(EnvSource::DorsfilePath, vec![("PATH".to_string(), path)]))
+}
+
+/// A single assignment of an environment variable, as evaluated by bash
+#[derive(Debug, Clone)]
+pub struct EnvVar {
+    pub name: String,
+    pub value: String,
+    /// `value`, with the values of any secret variables replaced by `***`
+    pub masked_value: String,
+    /// Where the variable was assigned, e.g. `builtin` or `member Dorsfile`
+    pub source: String,
+    /// Whether a later assignment replaces this one
+    pub overridden: bool,
+}
+
+/// Every environment variable assignment for `dir`, from lowest to highest precedence.
+/// When a task is given, the variables specific to that task are included.
+pub fn resolved_env<P: AsRef<Path>>(
+    dir: P,
+    task_name: Option<&str>,
+    options: &RunOptions,
+) -> Result<Vec<EnvVar>, Box<dyn Error>> {
+    let dir = dir.as_ref();
+    let overrides = options.env_overrides()?;
+    let mut workspace = CargoWorkspaceInfo::new(dir);
+    let dorsfiles = DorsfileGetter::new(&mut workspace, options)?;
+    let dorsfile = dorsfiles.get(dir)?;
+    let (env, secret_env) = match task_name {
+        Some(task_name) => {
+            let task = dorsfile
+                .task
+                .get(task_name)
+                .ok_or_else(|| DorsError::NoTask(task_name.to_string()))?;
+            (
+                task_env(task_name, task, &dorsfile, &overrides),
+                secret_env(task, &dorsfile),
+            )
+        }
+        None => {
+            let mut env = dorsfile.env.clone();
+            env.extend(dorsfile_path_env(&dorsfile));
+            env.extend(overrides);
+            (env, dorsfile.secret_env.clone())
+        }
+    };
+    let root = workspace.root.canonicalize().unwrap();
+    let assignments = env::evaluate(&env, dir)?;
+    // mask the final value of each secret, as evaluated alongside everything else
+    let secrets = mask::secret_patterns(secret_env.iter().filter_map(|secret| {
+        assignments
+            .iter()
+            .rev()
+            .find(|(name, _, _)| name == secret)
+            .map(|(_, value, _)| value.clone())
+    }));
+    Ok(assignments
+        .iter()
+        .enumerate()
+        .map(|(index, (name, value, source))| EnvVar {
+            name: name.clone(),
+            value: value.clone(),
+            masked_value: String::from_utf8(mask::mask(value.as_bytes(), &secrets)).unwrap(),
+            source: match source {
+                EnvSource::Builtin => "builtin".to_string(),
+                EnvSource::Dorsfile(path) => {
+                    let dir = path.parent().unwrap().canonicalize().unwrap();
+                    if Some(path) == user_dorsfile_path().as_ref() {
+                        "user Dorsfile".to_string()
+                    } else if dir == root {
+                        "workspace Dorsfile".to_string()
+                    } else if dir.starts_with(&root) {
+                        "member Dorsfile".to_string()
+                    } else {
+                        "repository Dorsfile".to_string()
+                    }
+                }
+                EnvSource::DorsfilePath => "Dorsfile path entries".to_string(),
+                EnvSource::Task(task_name) => format!("task `{}`", task_name),
+                EnvSource::EnvFile(path) => format!("env file `{}`", path.to_str().unwrap()),
+                EnvSource::CommandLine => "command line".to_string(),
+            },
+            overridden: assignments[index + 1..]
+                .iter()
+                .any(|(later, _, _)| later == name),
+        })
+        .collect())
+}
+
 pub fn all_tasks<P: AsRef<Path>>(dir: P) -> Result<Vec<String>, Box<dyn Error>> {
-    let workspace = CargoWorkspaceInfo::new(dir.as_ref());
-    let dorsfiles = DorsfileGetter::new(&workspace.root)?;
+    Ok(list_tasks(dir, &RunOptions::default())?
+        .into_iter()
+        .map(|task| task.name)
+        .collect())
+}
+
+/// A task available from some directory
+#[derive(Debug, Clone)]
+pub struct ListedTask {
+    pub name: String,
+    /// Whether the task is from the user's own Dorsfile
+    pub user: bool,
+}
+
+/// Every task available from `dir`, in no particular order
+pub fn list_tasks<P: AsRef<Path>>(
+    dir: P,
+    options: &RunOptions,
+) -> Result<Vec<ListedTask>, Box<dyn Error>> {
+    let mut workspace = CargoWorkspaceInfo::new(dir.as_ref());
+    let dorsfiles = DorsfileGetter::new(&mut workspace, options)?;
     Ok(dorsfiles
         .get(dir.as_ref())?
         .task
-        .keys()
-        .cloned()
-        .collect::<Vec<_>>())
+        .into_iter()
+        .map(|(name, task)| ListedTask {
+            name,
+            user: task.user,
+        })
+        .collect())
+}
+
+/// How to invoke a task, including any parameters it accepts
+pub fn task_usage<P: AsRef<Path>>(
+    task_name: &str,
+    dir: P,
+    options: &RunOptions,
+) -> Result<String, Box<dyn Error>> {
+    let mut workspace = CargoWorkspaceInfo::new(dir.as_ref());
+    let dorsfiles = DorsfileGetter::new(&mut workspace, options)?;
+    let dorsfile = dorsfiles.get(dir.as_ref())?;
+    let task = dorsfile
+        .task
+        .get(task_name)
+        .ok_or_else(|| DorsError::NoTask(task_name.to_string()))?;
+    Ok(params::usage(task_name, task))
 }
 
 fn print_task(task_name: &str, path: &Path) {
@@ -206,30 +748,91 @@ pub fn run<P: AsRef<Path>>(task: &str, dir: P) -> Result<ExitStatus, Box<dyn Err
     run_with_args(task, dir, &[])
 }
 
+/// Options that change how tasks are ran, usually set from the command line
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Run every member of a `run-from = "members"` task, even after one fails
+    pub keep_going: bool,
+    /// Variables that override those from Dorsfiles, in the order they are given
+    pub env: Vec<(String, String)>,
+    /// Dotenv-style files that override Dorsfiles, but not `env`
+    pub env_files: Vec<PathBuf>,
+    /// Include the user's own Dorsfile, as the command line does unless given
+    /// `--no-user-dorsfile`
+    pub user_dorsfile: bool,
+}
+
+impl RunOptions {
+    fn env_overrides(&self) -> Result<Vec<EnvTable>, DorsError> {
+        let mut overrides = self
+            .env_files
+            .iter()
+            .map(|path| env::load_env_file(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some((key, _)) = self.env.iter().find(|(key, _)| !env::is_valid_name(key)) {
+            return Err(DorsError::InvalidEnvName(key.clone()));
+        }
+        overrides.push(EnvTable::new(
+            EnvSource::CommandLine,
+            self.env
+                .iter()
+                .map(|(key, value)| (key.clone(), value.as_str().into())),
+        ));
+        Ok(overrides)
+    }
+}
+
+/// A task name, along with the Dorsfile it is defined in and the directory it runs from
+type ResolvedTask<'a, 'b> = (&'a str, Cow<'b, Dorsfile>, Cow<'b, Path>);
+
+/// A before or after that has been ran, by its name, directory, and arguments
+type RanTask = (String, PathBuf, Vec<String>);
+
+fn ran_task(task_name: &str, dir: &Path, args: Vec<String>) -> RanTask {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    (task_name.to_string(), dir, args)
+}
+
+/// A member, along with its Dorsfile, if it could be loaded
+type MemberDorsfile<'a> = (&'a String, &'a Member, Result<Dorsfile, Box<dyn Error>>);
+
 struct TaskRunner {
     workspace: CargoWorkspaceInfo,
     dorsfiles: DorsfileGetter,
+    options: RunOptions,
+    env_overrides: Vec<EnvTable>,
 }
 
 pub fn run_with_args<P: AsRef<Path>>(
     task: &str,
     dir: P,
     args: &[String],
+) -> Result<ExitStatus, Box<dyn Error>> {
+    run_with_options(task, dir, args, &RunOptions::default())
+}
+
+pub fn run_with_options<P: AsRef<Path>>(
+    task: &str,
+    dir: P,
+    args: &[String],
+    options: &RunOptions,
 ) -> Result<ExitStatus, Box<dyn Error>> {
     let dir = dir.as_ref();
-    let workspace = CargoWorkspaceInfo::new(&dir);
-    let dorsfiles = DorsfileGetter::new(&workspace.root)?;
-    let dorsfile = dorsfiles.get(&dir)?;
+    let (runner, dorsfile) = TaskRunner::new(dir, options)?;
 
-    TaskRunner {
-        workspace,
-        dorsfiles,
+    // check every task's arguments and required variables up front, so that nothing
+    // runs half way
+    let mut unmet = vec![];
+    runner.check_task_graph(task, &dorsfile, dir, args, &mut HashSet::new(), &mut unmet)?;
+    if !unmet.is_empty() {
+        return Err(DorsError::UnmetEnvRequirements(unmet).into());
     }
+
     // seed recursion
-    .run_task(
+    runner.run_task(
         task,
         &dorsfile,
-        &dir,
+        dir,
         args,
         &mut HashSet::new(),
         &mut HashSet::new(),
@@ -237,32 +840,241 @@ pub fn run_with_args<P: AsRef<Path>>(
 }
 
 impl TaskRunner {
+    /// A runner for the workspace containing `dir`, along with the Dorsfile for `dir`
+    fn new(dir: &Path, options: &RunOptions) -> Result<(TaskRunner, Dorsfile), Box<dyn Error>> {
+        let mut workspace = CargoWorkspaceInfo::new(dir);
+        let dorsfiles = DorsfileGetter::new(&mut workspace, options)?;
+        let dorsfile = dorsfiles.get(dir)?;
+        let runner = TaskRunner {
+            workspace,
+            dorsfiles,
+            options: options.clone(),
+            env_overrides: options.env_overrides()?,
+        };
+        Ok((runner, dorsfile))
+    }
+
+    /// Fails on the first task, of this task or anything it may run, given invalid
+    /// arguments, and collects every required variable that is unset or not one of its
+    /// allowed values
+    fn check_task_graph(
+        &self,
+        task_name: &str,
+        dorsfile: &Dorsfile,
+        dir: &Path,
+        args: &[String],
+        visited: &mut HashSet<RanTask>,
+        unmet: &mut Vec<UnmetEnvRequirement>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !visited.insert(ran_task(task_name, dir, args.to_vec())) {
+            return Ok(());
+        }
+        let task = dorsfile
+            .task
+            .get(task_name)
+            .ok_or_else(|| DorsError::NoTask(task_name.to_string()))?;
+        let (params, _) = params::parse(task_name, task, args)?;
+
+        for before in task.before.iter().flatten() {
+            let args = before.args(args);
+            let (before, dorsfile, dir) =
+                self.resolve_dependency(task_name, before.task(), dorsfile, dir)?;
+            self.check_task_graph(before, &dorsfile, &dir, &args, visited, unmet)?;
+        }
+
+        let at_root = dir.canonicalize().unwrap() == self.workspace.root.canonicalize().unwrap();
+        match task.run_from {
+            // each member's own task is checked against that member's environment
+            Run::Members if at_root => {
+                for (_, member, dorsfile) in self.member_dorsfiles(task_name, task)? {
+                    self.check_task_graph(
+                        task_name,
+                        &dorsfile?,
+                        &member.path,
+                        args,
+                        visited,
+                        unmet,
+                    )?;
+                }
+            }
+            Run::Members if matches!(task.outside_root, OutsideRoot::Error) => {}
+            // each workspace's own task is checked against that workspace
+            Run::Workspaces | Run::WorkspaceMembers => {
+                let paths = dorsfile
+                    .workspaces
+                    .iter()
+                    .flat_map(|workspaces| workspaces.paths.iter())
+                    .filter(|path| path.is_dir());
+                for path in paths {
+                    let (runner, dorsfile) = TaskRunner::new(path, &self.options)?;
+                    runner.check_task_graph(
+                        task_name,
+                        &dorsfile,
+                        path,
+                        args,
+                        &mut HashSet::new(),
+                        unmet,
+                    )?;
+                }
+            }
+            // ran with the member's environment, so checked against it too
+            Run::Member(ref member) => {
+                let member =
+                    self.workspace
+                        .members
+                        .get(member)
+                        .ok_or_else(|| DorsError::UnknownMember {
+                            task: task_name.to_string(),
+                            member: member.clone(),
+                            suggestion: self.workspace.suggest_member(member),
+                        })?;
+                let env = self.task_env_with_params(
+                    task_name,
+                    task,
+                    &self.dorsfiles.get(&member.path)?,
+                    &params,
+                );
+                self.check_requires_env(task_name, task, &env, &member.path, unmet)?;
+            }
+            _ => {
+                let env = self.task_env_with_params(task_name, task, dorsfile, &params);
+                self.check_requires_env(task_name, task, &env, dir, unmet)?;
+            }
+        }
+
+        for after in task.after.iter().flatten() {
+            let args = after.args(args);
+            let (after, dorsfile, dir) =
+                self.resolve_dependency(task_name, after.task(), dorsfile, dir)?;
+            self.check_task_graph(after, &dorsfile, &dir, &args, visited, unmet)?;
+        }
+        Ok(())
+    }
+
+    /// Collects every variable the task requires that is unset or not one of its allowed
+    /// values in `env`
+    fn check_requires_env(
+        &self,
+        task_name: &str,
+        task: &Task,
+        env: &[EnvTable],
+        dir: &Path,
+        unmet: &mut Vec<UnmetEnvRequirement>,
+    ) -> Result<(), DorsError> {
+        let requires_env = match task.requires_env {
+            Some(ref requires_env) => requires_env,
+            None => return Ok(()),
+        };
+        let requirements = requires_env.requirements();
+        let names = requirements
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        // evaluated apart from the run itself, so `shell` values are ran twice
+        let values = env::lookup(env, dir, &names)?;
+        requirements
+            .into_iter()
+            .zip(values)
+            .filter(|((_, allowed), found)| match (allowed, found) {
+                (_, None) => true,
+                (Some(allowed), Some(found)) => !allowed.contains(found),
+                (None, Some(_)) => false,
+            })
+            .map(|((name, allowed), found)| UnmetEnvRequirement {
+                task: task_name.to_string(),
+                name: name.clone(),
+                found,
+                allowed: allowed.cloned(),
+            })
+            .for_each(|requirement| {
+                if !unmet.contains(&requirement) {
+                    unmet.push(requirement);
+                }
+            });
+        Ok(())
+    }
+
+    /// Every environment variable a task is ran with, including its parameters
+    fn task_env_with_params(
+        &self,
+        task_name: &str,
+        task: &Task,
+        dorsfile: &Dorsfile,
+        params: &IndexMap<String, EnvValue>,
+    ) -> Vec<EnvTable> {
+        let mut env = task_env(task_name, task, dorsfile, &self.env_overrides);
+        // parameters are given on the command line, but `--env` still takes precedence
+        env.insert(
+            env.len() - self.env_overrides.len(),
+            EnvTable::new(EnvSource::Task(task_name.to_string()), params.clone()),
+        );
+        env
+    }
+
+    /// The task a before or after refers to, along with the Dorsfile and directory it is from
+    fn resolve_dependency<'a, 'b>(
+        &self,
+        task_name: &str,
+        dependency: &'a str,
+        dorsfile: &'b Dorsfile,
+        dir: &'b Path,
+    ) -> Result<ResolvedTask<'a, 'b>, Box<dyn Error>> {
+        Ok(match self.workspace.locate_task(dependency) {
+            Some((task, path)) => (
+                task,
+                Cow::Owned(self.dorsfiles.get(&path)?),
+                Cow::Owned(path),
+            ),
+            None => {
+                // a missing task that looks qualified is most likely a misspelled member
+                match dependency.split_once(':') {
+                    Some((member, _)) if !dorsfile.task.contains_key(dependency) => {
+                        return Err(DorsError::UnknownMember {
+                            task: task_name.to_string(),
+                            member: member.to_string(),
+                            suggestion: self.workspace.suggest_member(member),
+                        }
+                        .into())
+                    }
+                    _ => (dependency, Cow::Borrowed(dorsfile), Cow::Borrowed(dir)),
+                }
+            }
+        })
+    }
+
     fn run_task(
         &self,
         task_name: &str,
         dorsfile: &Dorsfile,
         dir: &Path,
         args: &[String],
-        already_ran_befores: &mut HashSet<String>,
-        already_ran_afters: &mut HashSet<String>,
+        already_ran_befores: &mut HashSet<RanTask>,
+        already_ran_afters: &mut HashSet<RanTask>,
     ) -> Result<ExitStatus, Box<dyn Error>> {
         let task = dorsfile
             .task
             .get(task_name)
             .ok_or_else(|| DorsError::NoTask(task_name.to_string()))?;
+        let (params, task_args) = params::parse(task_name, task, args)?;
 
         // Handle befores
         if let Some(ref befores) = task.before {
             if let Some(befores_result) = befores
                 .iter()
-                .filter_map(|before_task_name| {
-                    if !already_ran_befores.contains(before_task_name) {
-                        already_ran_befores.insert(before_task_name.into());
+                .filter_map(|before| {
+                    let (name, dorsfile, dir) =
+                        match self.resolve_dependency(task_name, before.task(), dorsfile, dir) {
+                            Ok(resolved) => resolved,
+                            Err(e) => return Some(Err(e)),
+                        };
+                    // the same task may be ran more than once, so long as its args differ
+                    let key = ran_task(name, &dir, before.args(args));
+                    if already_ran_befores.insert(key.clone()) {
                         Some(self.run_task(
-                            before_task_name,
-                            dorsfile,
-                            dir,
-                            &[],
+                            name,
+                            &dorsfile,
+                            &dir,
+                            &key.2,
                             already_ran_befores,
                             &mut HashSet::new(),
                         ))
@@ -280,72 +1092,112 @@ impl TaskRunner {
         }
 
         // run command
+        let env_from =
+            |dorsfile: &Dorsfile| self.task_env_with_params(task_name, task, dorsfile, &params);
+        let env = env_from(dorsfile);
+        let secret_env = secret_env(task, dorsfile);
         let result = match task.run_from {
-            Run::Here => {
-                print_task(task_name, &dir);
-                run_command(&task.command, dir, &dorsfile.env, args)
-            }
+            Run::Here => self.run_in(task_name, task, dir, &env, &secret_env, &task_args)?,
             Run::WorkspaceRoot => {
                 // TODO error gracefully when someone messes this up
-                let path = &self.workspace.root;
-                print_task(task_name, path);
-                run_command(&task.command, path, &dorsfile.env, args)
+                self.run_in(
+                    task_name,
+                    task,
+                    &self.workspace.root,
+                    &env,
+                    &secret_env,
+                    &task_args,
+                )?
             }
             Run::Members => {
                 if dir.canonicalize().unwrap() != self.workspace.root.canonicalize().unwrap() {
-                    panic!("cannot run from members from outside workspace root");
-                }
-                self.workspace
-                    .members
-                    .iter()
-                    .filter_map(|(name, path)| {
-                        let short_path = if path.is_relative() {
-                            path
-                        } else {
-                            path.strip_prefix(&self.workspace.root).unwrap()
-                        };
-                        match task.member_modifiers {
-                            Some(ref modifiers) => match modifiers {
-                                MemberModifiers::SkipMembers(skips) => {
-                                    if skips.contains(name)
-                                        || skips.contains(&short_path.to_str().unwrap().to_string())
-                                    {
-                                        None
-                                    } else {
-                                        Some(path)
-                                    }
-                                }
-                                MemberModifiers::OnlyMembers(onlys) => {
-                                    if onlys.contains(name)
-                                        || onlys.contains(&short_path.to_str().unwrap().to_string())
-                                    {
-                                        Some(path)
-                                    } else {
-                                        None
-                                    }
-                                }
-                            },
-                            None => Some(path),
+                    match task.outside_root {
+                        OutsideRoot::CurrentMember => {
+                            // Run the command directly, as this member's task may be
+                            // the one asking to run from members
+                            let current = self.workspace.member_containing(dir)?;
+                            let (_, member) = self
+                                .workspace
+                                .select_members(task_name, &task.member_modifiers)?
+                                .into_iter()
+                                .find(|(name, _)| *name == current)
+                                .ok_or_else(|| DorsError::NoMembersMatched(task_name.into()))?;
+                            self.run_in(
+                                task_name,
+                                task,
+                                &member.path,
+                                &env,
+                                &secret_env,
+                                &task_args,
+                            )?
                         }
-                    })
-                    .map(|path| {
-                        let dorsfile = self.dorsfiles.get(&path)?;
-                        self.run_task(
-                            task_name,
-                            &dorsfile,
-                            &path,
-                            args,
-                            &mut HashSet::new(),
-                            &mut HashSet::new(),
-                        )
-                    })
-                    .take_while_last(|result| result.is_ok() && result.as_ref().unwrap().success())
-                    .last()
-                    .unwrap()?
+                        OutsideRoot::Error => {
+                            return Err(
+                                DorsError::MembersOutsideWorkspaceRoot(task_name.into()).into()
+                            )
+                        }
+                    }
+                } else if self.options.keep_going || task.keep_going {
+                    self.run_members_keep_going(task_name, task, args)?
+                } else {
+                    self.member_dorsfiles(task_name, task)?
+                        .into_iter()
+                        .map(|(_, member, dorsfile)| {
+                            self.run_task(
+                                task_name,
+                                &dorsfile?,
+                                &member.path,
+                                args,
+                                &mut HashSet::new(),
+                                &mut HashSet::new(),
+                            )
+                        })
+                        .take_while_last(|result| {
+                            result.is_ok() && result.as_ref().unwrap().success()
+                        })
+                        .last()
+                        .unwrap()?
+                }
+            }
+            Run::Member(ref member) => {
+                let member =
+                    self.workspace
+                        .members
+                        .get(member)
+                        .ok_or_else(|| DorsError::UnknownMember {
+                            task: task_name.to_string(),
+                            member: member.clone(),
+                            suggestion: self.workspace.suggest_member(member),
+                        })?;
+                // ran as if from the member, with its environment and builtins
+                let member_dorsfile = self.dorsfiles.get(&member.path)?;
+                self.run_in(
+                    task_name,
+                    task,
+                    &member.path,
+                    &env_from(&member_dorsfile),
+                    &crate::secret_env(task, &member_dorsfile),
+                    &task_args,
+                )?
             }
-            Run::Path(ref target_path) => {
-                print_task(task_name, &target_path);
-                run_command(&task.command, &dir.join(target_path), &dorsfile.env, args)
+            Run::Paths(ref paths) => {
+                let paths = paths.iter().map(|path| dir.join(path)).collect::<Vec<_>>();
+                self.run_in_each(task_name, task, &paths, &env, &secret_env, &task_args)?
+            }
+            Run::Glob(ref pattern) => {
+                let paths = glob::glob_dirs(dir, pattern);
+                self.run_in_each(task_name, task, &paths, &env, &secret_env, &task_args)?
+            }
+            Run::Path(ref target_path) => self.run_in(
+                task_name,
+                task,
+                &dir.join(target_path),
+                &env,
+                &secret_env,
+                &task_args,
+            )?,
+            Run::Workspaces | Run::WorkspaceMembers => {
+                self.run_in_workspaces(task_name, task, dorsfile, args)?
             }
         };
 
@@ -357,14 +1209,19 @@ impl TaskRunner {
         if let Some(ref afters) = task.after {
             if let Some(afters_result) = afters
                 .iter()
-                .filter_map(|after_task_name| {
-                    if !already_ran_afters.contains(after_task_name) {
-                        already_ran_afters.insert(after_task_name.into());
+                .filter_map(|after| {
+                    let (name, dorsfile, dir) =
+                        match self.resolve_dependency(task_name, after.task(), dorsfile, dir) {
+                            Ok(resolved) => resolved,
+                            Err(e) => return Some(Err(e)),
+                        };
+                    let key = ran_task(name, &dir, after.args(args));
+                    if already_ran_afters.insert(key.clone()) {
                         Some(self.run_task(
-                            after_task_name,
-                            dorsfile,
-                            dir,
-                            &[],
+                            name,
+                            &dorsfile,
+                            &dir,
+                            &key.2,
                             &mut HashSet::new(),
                             already_ran_afters,
                         ))
@@ -383,6 +1240,258 @@ impl TaskRunner {
 
         Ok(result)
     }
+
+    /// Members a `run-from = "members"` task runs on, along with their Dorsfiles. Members
+    /// that disable the task, or don't inherit it, are left out.
+    fn member_dorsfiles(
+        &self,
+        task_name: &str,
+        task: &Task,
+    ) -> Result<Vec<MemberDorsfile<'_>>, DorsError> {
+        let members = self
+            .workspace
+            .select_members(task_name, &task.member_modifiers)?
+            .into_iter()
+            .map(|(name, member)| (name, member, self.dorsfiles.get(&member.path)))
+            .filter(|(_, _, dorsfile)| {
+                dorsfile
+                    .as_ref()
+                    .map_or(true, |dorsfile| dorsfile.task.contains_key(task_name))
+            })
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            return Err(DorsError::NoMembersMatched(task_name.to_string()));
+        }
+        Ok(members)
+    }
+
+    /// Runs the task on every member regardless of failures, then prints a summary.
+    /// Returns the first error or failure encountered, if any.
+    fn run_members_keep_going(
+        &self,
+        task_name: &str,
+        task: &Task,
+        args: &[String],
+    ) -> Result<ExitStatus, Box<dyn Error>> {
+        let mut summary = Summary::new(task_name);
+        let results = self
+            .member_dorsfiles(task_name, task)?
+            .into_iter()
+            .map(|(name, member, dorsfile)| {
+                let start = Instant::now();
+                let result = dorsfile.and_then(|dorsfile| {
+                    self.run_task(
+                        task_name,
+                        &dorsfile,
+                        &member.path,
+                        args,
+                        &mut HashSet::new(),
+                        &mut HashSet::new(),
+                    )
+                });
+                summary.push(name, &result, start.elapsed());
+                result
+            })
+            .collect::<Vec<_>>();
+        summary.print();
+        first_failure(results)
+    }
+
+    /// Runs the task from the root of every workspace listed in `[workspaces]`, or across
+    /// their members for `workspace-members`, each with its own members and Dorsfiles
+    fn run_in_workspaces(
+        &self,
+        task_name: &str,
+        task: &Task,
+        dorsfile: &Dorsfile,
+        args: &[String],
+    ) -> Result<ExitStatus, Box<dyn Error>> {
+        let paths = match dorsfile.workspaces {
+            Some(ref workspaces) if !workspaces.paths.is_empty() => &workspaces.paths,
+            _ => return Err(DorsError::NoRunPaths(task_name.to_string()).into()),
+        };
+        let run = |path: &PathBuf| {
+            if !path.is_dir() {
+                return Err(DorsError::MissingRunPath {
+                    task: task_name.to_string(),
+                    path: path.clone(),
+                }
+                .into());
+            }
+            // the task graph was already checked along with everything else
+            let (runner, dorsfile) = TaskRunner::new(path, &self.options)?;
+            runner.run_task(
+                task_name,
+                &dorsfile,
+                path,
+                args,
+                &mut HashSet::new(),
+                &mut HashSet::new(),
+            )
+        };
+        if self.options.keep_going || task.keep_going {
+            let mut summary = Summary::new(task_name);
+            let results = paths
+                .iter()
+                .map(|path| {
+                    let start = Instant::now();
+                    let result = run(path);
+                    let label = path.strip_prefix(&self.workspace.root).unwrap_or(path);
+                    summary.push(label.to_str().unwrap(), &result, start.elapsed());
+                    result
+                })
+                .collect::<Vec<_>>();
+            summary.print();
+            first_failure(results)
+        } else {
+            paths
+                .iter()
+                .map(run)
+                .take_while_last(|result| result.is_ok() && result.as_ref().unwrap().success())
+                .last()
+                .unwrap()
+        }
+    }
+
+    /// Runs a task's command from each of `paths` in turn, like a `run-from = "members"` task
+    fn run_in_each(
+        &self,
+        task_name: &str,
+        task: &Task,
+        paths: &[PathBuf],
+        env: &[EnvTable],
+        secret_env: &[String],
+        args: &[String],
+    ) -> Result<ExitStatus, Box<dyn Error>> {
+        if paths.is_empty() {
+            return Err(DorsError::NoRunPaths(task_name.to_string()).into());
+        }
+        if self.options.keep_going || task.keep_going {
+            let mut summary = Summary::new(task_name);
+            let results = paths
+                .iter()
+                .map(|path| {
+                    let start = Instant::now();
+                    let result = self.run_in(task_name, task, path, env, secret_env, args);
+                    let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                    let label = path.strip_prefix(&self.workspace.root).unwrap_or(&path);
+                    summary.push(label.to_str().unwrap(), &result, start.elapsed());
+                    result
+                })
+                .collect::<Vec<_>>();
+            summary.print();
+            first_failure(results)
+        } else {
+            paths
+                .iter()
+                .map(|path| self.run_in(task_name, task, path, env, secret_env, args))
+                .take_while_last(|result| result.is_ok() && result.as_ref().unwrap().success())
+                .last()
+                .unwrap()
+        }
+    }
+
+    /// Runs a task's command from `path`, once for every cell if the task has a matrix
+    fn run_in(
+        &self,
+        task_name: &str,
+        task: &Task,
+        path: &Path,
+        env: &[EnvTable],
+        secret_env: &[String],
+        args: &[String],
+    ) -> Result<ExitStatus, Box<dyn Error>> {
+        if !path.is_dir() {
+            return Err(DorsError::MissingRunPath {
+                task: task_name.to_string(),
+                path: path.to_path_buf(),
+            }
+            .into());
+        }
+        let cells = match task.matrix {
+            None => {
+                print_task(task_name, path);
+                return Ok(run_command(&task.command, path, env, secret_env, args));
+            }
+            Some(Matrix::Builtin(BuiltinMatrix::Features)) => {
+                let name = self.workspace.member_containing(path)?;
+                matrix::feature_cells(
+                    &self.workspace.members[name].features,
+                    &task.feature_combinations,
+                    task.powerset_depth,
+                )
+                .into_iter()
+                .map(|mut cell| {
+                    cell.label = format!("{} {}", name, cell.label);
+                    cell
+                })
+                .collect::<Vec<_>>()
+            }
+            Some(Matrix::Axes(ref axes)) => matrix::axes_cells(task_name, axes)?,
+        };
+
+        let mut summary = Summary::new(task_name);
+        let mut results = vec![];
+        for cell in cells {
+            // cells are builtins, so anything from the command line still takes precedence
+            let mut env = env.to_vec();
+            env.insert(
+                env.len() - self.env_overrides.len(),
+                EnvTable::new(EnvSource::Builtin, cell.env),
+            );
+            print_task(&format!("{} [{}]", task_name, cell.label), path);
+            let start = Instant::now();
+            let result = Ok(run_command(&task.command, path, &env, secret_env, args));
+            summary.push(&cell.label, &result, start.elapsed());
+            let failed = !result.as_ref().unwrap().success();
+            results.push(result);
+            if failed && !(self.options.keep_going || task.keep_going) {
+                break;
+            }
+        }
+        summary.print();
+        first_failure(results)
+    }
+}
+
+/// The first error or failure among `results`, otherwise the last success
+fn first_failure(
+    mut results: Vec<Result<ExitStatus, Box<dyn Error>>>,
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let first_failure = results
+        .iter()
+        .position(|result| result.is_err())
+        .or_else(|| {
+            results
+                .iter()
+                .position(|result| !result.as_ref().unwrap().success())
+        });
+    match first_failure {
+        Some(index) => results.swap_remove(index),
+        None => results.pop().unwrap(),
+    }
+}
+
+fn run_options(matches: &clap::ArgMatches) -> RunOptions {
+    RunOptions {
+        keep_going: matches.is_present("keep-going"),
+        env: matches
+            .values_of("env")
+            .map(|values| {
+                values
+                    .map(|value| {
+                        let (key, value) = value.split_once('=').unwrap();
+                        (key.to_string(), value.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+        env_files: matches
+            .values_of("env-file")
+            .map(|values| values.map(PathBuf::from).collect())
+            .unwrap_or_default(),
+        user_dorsfile: !matches.is_present("no-user-dorsfile"),
+    }
 }
 
 #[allow(clippy::print_stdout)]
@@ -391,16 +1500,74 @@ pub fn process_cmd<'a>(matches: &clap::ArgMatches<'a>) -> i32 {
         Some(directory) => directory.into(),
         None => std::env::current_dir().unwrap(),
     };
+
+    if let Some(env_matches) = matches.subcommand_matches("env") {
+        let directory = env_matches
+            .value_of("subdirectory")
+            .map_or(directory, |directory| directory.into());
+        let options = run_options(env_matches);
+        warn_if_shadowed("env", &directory, &options);
+        let vars = match resolved_env(directory, env_matches.value_of("task"), &options) {
+            Ok(vars) => vars,
+            Err(e) => {
+                println!("{}", e);
+                return 1;
+            }
+        };
+        if env_matches.is_present("export") {
+            vars.iter().filter(|var| !var.overridden).for_each(|var| {
+                println!(
+                    "export {}={}",
+                    var.name,
+                    env::EnvValue::from(var.value.as_str()).to_shell()
+                )
+            });
+        } else {
+            vars.iter().for_each(|var| {
+                let line = format!("{}={}", var.name, var.masked_value);
+                if var.overridden {
+                    println!("{}  ({}, overridden)", line.dimmed(), var.source);
+                } else {
+                    println!("{}  ({})", line, var.source.dimmed());
+                }
+            });
+        }
+        return 0;
+    }
+
+    if let Some(help_matches) = matches.subcommand_matches("help") {
+        let options = run_options(help_matches);
+        warn_if_shadowed("help", &directory, &options);
+        match task_usage(help_matches.value_of("TASK").unwrap(), directory, &options) {
+            Ok(usage) => {
+                println!("Usage: {}", usage);
+                return 0;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return 1;
+            }
+        }
+    }
+
     if matches.is_present("list") {
-        let mut tasks = match all_tasks(directory) {
+        let mut tasks = match list_tasks(directory, &run_options(matches)) {
             Ok(tasks) => tasks,
             Err(e) => {
                 println!("{}", e);
                 return 1;
             }
         };
-        tasks.sort();
-        tasks.iter().for_each(|task| println!("{}", task));
+        tasks.sort_by(|a, b| a.name.cmp(&b.name));
+        // completions read this list, so only mark user tasks for people
+        let marked = std::io::stdout().is_terminal();
+        tasks.iter().for_each(|task| {
+            if task.user && marked {
+                println!("{}  {}", task.name, "(user)".dimmed());
+            } else {
+                println!("{}", task.name);
+            }
+        });
         return 0;
     }
 
@@ -410,12 +1577,11 @@ pub fn process_cmd<'a>(matches: &clap::ArgMatches<'a>) -> i32 {
         return 0;
     }
 
-    if let Some(task) = matches.value_of("TASK") {
-        let args = match matches.values_of("TASK_ARGS") {
-            Some(values) => values.map(|s| s.to_string()).collect(),
-            None => vec![],
-        };
-        match run_with_args(&task, directory, &args) {
+    if let Some(mut values) = matches.values_of("TASK") {
+        let task = values.next().unwrap();
+        let args = values.map(|s| s.to_string()).collect::<Vec<_>>();
+        let options = run_options(matches);
+        match run_with_options(task, directory, &args, &options) {
             Ok(resp) => return resp.code().unwrap(),
             Err(e) => {
                 println!("{}", e);
@@ -424,25 +1590,36 @@ pub fn process_cmd<'a>(matches: &clap::ArgMatches<'a>) -> i32 {
         }
     }
 
-    let mut tasks = match all_tasks(directory) {
+    let mut tasks = match list_tasks(directory, &run_options(matches)) {
         Ok(tasks) => tasks,
         Err(e) => {
             println!("{}", e);
             return 1;
         }
     };
-    tasks.sort();
-
-    if matches.is_present("list") {
-        tasks.iter().for_each(|task| println!("{}", task));
-        return 0;
-    }
+    tasks.sort_by(|a, b| a.name.cmp(&b.name));
 
     println!("{}: Please select a task to run:", "Error".red());
-    tasks.iter().for_each(|task| println!("{}", task.bold()));
+    tasks
+        .iter()
+        .for_each(|task| println!("{}", task.name.bold()));
     1
 }
 
+/// Dors' own subcommands take precedence over tasks of the same name, so point out any
+/// task that the command line can't reach
+fn warn_if_shadowed(subcommand: &str, directory: &Path, options: &RunOptions) {
+    if let Ok(tasks) = list_tasks(directory, options) {
+        if tasks.iter().any(|task| task.name == subcommand) {
+            eprintln!(
+                "{}: task `{1}` can't be ran from the command line, as `dors {1}` is a dors command",
+                "Warning".yellow(),
+                subcommand
+            );
+        }
+    }
+}
+
 pub fn get_about() -> &'static str {
     "No-fuss workspace-aware task runner for rust"
 }
@@ -453,6 +1630,7 @@ pub fn set_app_options<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
         .setting(clap::AppSettings::TrailingVarArg)
         .setting(clap::AppSettings::ColoredHelp)
         .setting(clap::AppSettings::DontCollapseArgsInUsage)
+        .setting(clap::AppSettings::DisableHelpSubcommand)
         .about(get_about())
         .arg(
             clap::Arg::with_name("subdirectory")
@@ -467,7 +1645,7 @@ pub fn set_app_options<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
             clap::Arg::with_name("list")
                 .short("l")
                 .long("list")
-                .conflicts_with_all(&["TASK", "TASK_ARGS", "completions"])
+                .conflicts_with_all(&["TASK", "completions"])
                 .display_order(1)
                 .help("list all the available tasks"),
         )
@@ -482,11 +1660,84 @@ pub fn set_app_options<'a, 'b>(app: clap::App<'a, 'b>) -> clap::App<'a, 'b> {
                         command",
                 ),
         )
-        .arg(clap::Arg::with_name("TASK").help("the name of the task to run"))
         .arg(
-            clap::Arg::with_name("TASK_ARGS")
-                .help("arguments to pass to the task")
-                .requires("TASK")
+            clap::Arg::with_name("keep-going")
+                .short("k")
+                .long("keep-going")
+                .conflicts_with_all(&["list", "completions"])
+                .display_order(2)
+                .help("keep running remaining members after one fails, then print a summary"),
+        )
+        .arg(
+            clap::Arg::with_name("env")
+                .long("env")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .value_name("KEY=VALUE")
+                .validator(|value| match value.split_once('=') {
+                    Some((key, _)) if env::is_valid_name(key) => Ok(()),
+                    Some((key, _)) => Err(DorsError::InvalidEnvName(key.to_string()).to_string()),
+                    None => Err(format!("expected KEY=VALUE, found `{}`", value)),
+                })
+                .display_order(3)
+                .help("set an environment variable, overriding any from Dorsfiles"),
+        )
+        .arg(
+            clap::Arg::with_name("no-user-dorsfile")
+                .long("no-user-dorsfile")
+                .global(true)
+                .display_order(5)
+                .help("ignore tasks and environment from your own Dorsfile in ~/.config/dors"),
+        )
+        .arg(
+            clap::Arg::with_name("env-file")
+                .long("env-file")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .display_order(4)
+                .help("load environment variables from a file, overriding any from Dorsfiles"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("help")
+                .about("print the parameters a task accepts")
+                .arg(
+                    clap::Arg::with_name("TASK")
+                        .required(true)
+                        .help("the name of the task"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("env")
+                .about("print the environment a task is ran with, and where each variable is from")
+                .arg(
+                    clap::Arg::with_name("task")
+                        .long("task")
+                        .takes_value(true)
+                        .help("include variables specific to this task"),
+                )
+                .arg(
+                    clap::Arg::with_name("subdirectory")
+                        .short("d")
+                        .long("subdirectory")
+                        .takes_value(true)
+                        .help("resolve the environment for a specified subdirectory"),
+                )
+                .arg(
+                    clap::Arg::with_name("export")
+                        .long("export")
+                        .help("print the final values as a snippet that can be sourced by a shell"),
+                ),
+        )
+        .arg(
+            clap::Arg::with_name("TASK")
+                .help("the name of the task to run, followed by any arguments to pass to it")
+                .value_name("TASK [TASK_ARGS]")
+                // flags after the task name are the task's, even those dors takes itself
+                .allow_hyphen_values(true)
                 .multiple(true),
         )
 }
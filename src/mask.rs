@@ -0,0 +1,78 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+const MASK: &[u8] = b"***";
+
+/// Byte patterns to hide from output. Multi-line values are masked line by line,
+/// as output is only ever masked a line at a time.
+pub fn secret_patterns<I: IntoIterator<Item = String>>(values: I) -> Vec<Vec<u8>> {
+    let mut patterns = values
+        .into_iter()
+        .flat_map(|value| {
+            value
+                .lines()
+                .map(|line| line.as_bytes().to_vec())
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    // longest first, so that a secret containing another is masked whole
+    patterns.sort_by_key(|pattern| std::cmp::Reverse(pattern.len()));
+    patterns.dedup();
+    patterns
+}
+
+/// Replaces every occurrence of each pattern in `text` with `***`
+pub fn mask(text: &[u8], patterns: &[Vec<u8>]) -> Vec<u8> {
+    let mut masked = Vec::with_capacity(text.len());
+    let mut rest = text;
+    'outer: while !rest.is_empty() {
+        for pattern in patterns {
+            if rest.starts_with(pattern) {
+                masked.extend_from_slice(MASK);
+                rest = &rest[pattern.len()..];
+                continue 'outer;
+            }
+        }
+        masked.push(rest[0]);
+        rest = &rest[1..];
+    }
+    masked
+}
+
+/// Copies `reader` to `writer` line by line, masking each line as it goes
+pub fn copy_masked<R: Read, W: Write>(reader: R, mut writer: W, patterns: &[Vec<u8>]) {
+    let mut reader = BufReader::new(reader);
+    let mut line = vec![];
+    while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+        // the other end may have gone away, in which case output is dropped
+        let _ = writer
+            .write_all(&mask(&line, patterns))
+            .and_then(|_| writer.flush());
+        line.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask() {
+        let patterns = secret_patterns(vec![
+            "hunter2".to_string(),
+            "hunter".to_string(),
+            "".to_string(),
+            "-----BEGIN KEY-----\nabc123\n".to_string(),
+        ]);
+        assert_eq!(patterns.len(), 4);
+        let masked = |text: &str| String::from_utf8(mask(text.as_bytes(), &patterns)).unwrap();
+        assert_eq!(masked("token=hunter2!"), "token=***!");
+        assert_eq!(masked("hunter hunter22"), "*** ***2");
+        assert_eq!(masked("key: abc123\n"), "key: ***\n");
+        assert_eq!(masked("nothing to see"), "nothing to see");
+
+        let mut output = vec![];
+        copy_masked(&b"a hunter2\nb hunter2"[..], &mut output, &patterns);
+        assert_eq!(output, b"a ***\nb ***");
+    }
+}
@@ -0,0 +1,201 @@
+use crate::dorsfile::{AxesMatrix, FeatureCombinations};
+use crate::env::EnvValue;
+use crate::error::DorsError;
+use indexmap::IndexMap;
+
+/// A single run within a matrix, along with the environment that sets it apart
+#[derive(Debug, Clone)]
+pub struct Cell {
+    pub label: String,
+    pub env: IndexMap<String, EnvValue>,
+}
+
+/// Expands a member's features into one cell per combination. The `default`
+/// feature is left out, as tasks are expected to pass `--no-default-features`.
+pub fn feature_cells(
+    features: &[String],
+    combinations: &FeatureCombinations,
+    depth: Option<usize>,
+) -> Vec<Cell> {
+    let features = features
+        .iter()
+        .filter(|feature| *feature != "default")
+        .collect::<Vec<_>>();
+    let mut sets: Vec<Vec<&String>> = vec![vec![]];
+    match combinations {
+        FeatureCombinations::Each => {
+            sets.extend(features.iter().map(|feature| vec![*feature]));
+        }
+        FeatureCombinations::Powerset => {
+            let depth = depth.unwrap_or(features.len());
+            for feature in features {
+                let extended = sets
+                    .iter()
+                    .filter(|set| set.len() < depth)
+                    .map(|set| {
+                        let mut set = set.clone();
+                        set.push(feature);
+                        set
+                    })
+                    .collect::<Vec<_>>();
+                sets.extend(extended);
+            }
+            sets.sort_by_key(|set| set.len());
+        }
+    }
+    sets.into_iter()
+        .map(|set| {
+            let features = set
+                .iter()
+                .map(|feature| feature.as_str())
+                .collect::<Vec<_>>()
+                .join(",");
+            Cell {
+                label: if features.is_empty() {
+                    "no features".to_string()
+                } else {
+                    features.clone()
+                },
+                env: [("DORS_FEATURES".to_string(), features.into())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Expands user-defined axes into their cartesian product, minus any excluded combinations.
+/// Each axis is exported as `DORS_MATRIX_<AXIS>`.
+pub fn axes_cells(task_name: &str, matrix: &AxesMatrix) -> Result<Vec<Cell>, DorsError> {
+    if let Some(axis) = matrix
+        .exclude
+        .iter()
+        .flat_map(|exclude| exclude.keys())
+        .find(|axis| !matrix.axes.contains_key(*axis))
+    {
+        return Err(DorsError::UnknownMatrixAxis {
+            task: task_name.to_string(),
+            axis: axis.clone(),
+        });
+    }
+
+    let mut combinations: Vec<Vec<(&String, &String)>> = vec![vec![]];
+    for (axis, values) in &matrix.axes {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((axis, value));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    let cells = combinations
+        .into_iter()
+        .filter(|combination| {
+            !matrix.exclude.iter().any(|exclude| {
+                exclude
+                    .iter()
+                    .all(|(axis, value)| combination.contains(&(axis, value)))
+            })
+        })
+        .map(|combination| Cell {
+            label: combination
+                .iter()
+                .map(|(axis, value)| format!("{}={}", axis, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+            env: combination
+                .iter()
+                .map(|(axis, value)| {
+                    (
+                        format!("DORS_MATRIX_{}", axis.to_uppercase().replace('-', "_")),
+                        value.as_str().into(),
+                    )
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    if cells.is_empty() {
+        return Err(DorsError::EmptyMatrix(task_name.to_string()));
+    }
+    Ok(cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(cells: Vec<Cell>) -> Vec<String> {
+        cells.into_iter().map(|cell| cell.label).collect()
+    }
+
+    #[test]
+    fn test_feature_cells() {
+        let features = ["a", "b", "c", "default"]
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels(feature_cells(&features, &FeatureCombinations::Each, None)),
+            ["no features", "a", "b", "c"]
+        );
+        assert_eq!(
+            labels(feature_cells(
+                &features,
+                &FeatureCombinations::Powerset,
+                None
+            ))
+            .len(),
+            8
+        );
+        assert_eq!(
+            labels(feature_cells(
+                &features,
+                &FeatureCombinations::Powerset,
+                Some(2)
+            )),
+            ["no features", "a", "b", "c", "a,b", "a,c", "b,c"]
+        );
+    }
+
+    #[test]
+    fn test_axes_cells() {
+        let matrix: AxesMatrix = toml::from_str(
+            r#"
+target = ["arm", "x86"]
+profile = ["dev", "release"]
+exclude = [{ target = "arm", profile = "dev" }]
+"#,
+        )
+        .unwrap();
+        let cells = axes_cells("build", &matrix).unwrap();
+        assert_eq!(
+            cells.iter().map(|cell| &cell.label).collect::<Vec<_>>(),
+            [
+                "target=arm profile=release",
+                "target=x86 profile=dev",
+                "target=x86 profile=release",
+            ]
+        );
+        assert_eq!(cells[0].env["DORS_MATRIX_TARGET"], "arm".into());
+
+        let matrix: AxesMatrix =
+            toml::from_str("target = [\"arm\"]\nexclude = [{ target = \"arm\" }]").unwrap();
+        assert!(matches!(
+            axes_cells("build", &matrix),
+            Err(DorsError::EmptyMatrix(_))
+        ));
+
+        let matrix: AxesMatrix =
+            toml::from_str("target = [\"arm\"]\nexclude = [{ tagret = \"arm\" }]").unwrap();
+        assert!(matches!(
+            axes_cells("build", &matrix),
+            Err(DorsError::UnknownMatrixAxis { .. })
+        ));
+    }
+}
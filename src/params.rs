@@ -0,0 +1,216 @@
+use crate::dorsfile::{Param, ParamType, Task};
+use crate::env::EnvValue;
+use crate::error::DorsError;
+use indexmap::IndexMap;
+
+/// The variable a parameter is exported as, e.g. `DORS_PARAM_DRY_RUN` for `dry-run`
+pub fn param_env_name(name: &str) -> String {
+    format!("DORS_PARAM_{}", name.to_uppercase().replace('-', "_"))
+}
+
+/// Splits `args` into the values of a task's parameters and the arguments left over for `$@`.
+/// Tasks without parameters receive their arguments as given, less the first `--`.
+pub fn parse(
+    task_name: &str,
+    task: &Task,
+    args: &[String],
+) -> Result<(IndexMap<String, EnvValue>, Vec<String>), DorsError> {
+    if task.params.is_empty() {
+        // without parameters, a `--` only separates the task's arguments from the task name
+        let mut args = args.to_vec();
+        if let Some(index) = args.iter().position(|arg| arg == "--") {
+            args.remove(index);
+        }
+        return Ok((IndexMap::new(), args));
+    }
+    let usage_error = |message: String| DorsError::InvalidTaskArgs {
+        task: task_name.to_string(),
+        message,
+        usage: usage(task_name, task),
+    };
+
+    let mut given: IndexMap<&String, String> = IndexMap::new();
+    let mut rest = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            rest.extend(args.cloned());
+            break;
+        }
+        let flag = match arg.strip_prefix("--") {
+            Some(flag) => flag,
+            None => {
+                rest.push(arg.clone());
+                continue;
+            }
+        };
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (flag, None),
+        };
+        let (name, param) = task
+            .params
+            .get_key_value(name)
+            .ok_or_else(|| usage_error(format!("Unknown parameter `--{}`", name)))?;
+        let value = match (inline_value, &param.kind) {
+            (Some(value), _) => value,
+            (None, ParamType::Bool) => "true".to_string(),
+            (None, _) => args
+                .next()
+                .cloned()
+                .ok_or_else(|| usage_error(format!("Parameter `--{}` needs a value", name)))?,
+        };
+        given.insert(name, value);
+    }
+
+    let mut values = IndexMap::new();
+    for (name, param) in &task.params {
+        let value = match given.swap_remove(name) {
+            Some(value) => value,
+            None => match param.default {
+                Some(ref default) => default.to_text(),
+                None if matches!(param.kind, ParamType::Bool) => "false".to_string(),
+                None => return Err(usage_error(format!("Missing parameter `--{}`", name))),
+            },
+        };
+        validate(name, param, &value).map_err(usage_error)?;
+        values.insert(param_env_name(name), value.into());
+    }
+    Ok((values, rest))
+}
+
+fn validate(name: &str, param: &Param, value: &str) -> Result<(), String> {
+    let valid = match param.kind {
+        ParamType::String => true,
+        ParamType::Int => value.parse::<i64>().is_ok(),
+        ParamType::Float => value.parse::<f64>().is_ok(),
+        ParamType::Bool => value == "true" || value == "false",
+    };
+    if !valid {
+        return Err(format!(
+            "Parameter `--{}` expects {}, found `{}`",
+            name,
+            param.kind.describe(),
+            value
+        ));
+    }
+    match param.choices {
+        Some(ref choices) if !choices.iter().any(|choice| choice == value) => Err(format!(
+            "Parameter `--{}` must be one of {}, found `{}`",
+            name,
+            choices
+                .iter()
+                .map(|choice| format!("`{}`", choice))
+                .collect::<Vec<_>>()
+                .join(", "),
+            value
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A usage line for the task, followed by a description of each parameter
+pub fn usage(task_name: &str, task: &Task) -> String {
+    let flag = |name: &str, param: &Param| match param.kind {
+        ParamType::Bool => format!("--{}", name),
+        ref kind => format!("--{} <{}>", name, kind.name()),
+    };
+    let mut usage = format!("dors {}", task_name);
+    task.params.iter().for_each(|(name, param)| {
+        let required = param.default.is_none() && !matches!(param.kind, ParamType::Bool);
+        if required {
+            usage.push_str(&format!(" {}", flag(name, param)));
+        } else {
+            usage.push_str(&format!(" [{}]", flag(name, param)));
+        }
+    });
+    usage.push_str(" [ARGS]...");
+    if task.params.is_empty() {
+        return usage;
+    }
+
+    let flags = task
+        .params
+        .iter()
+        .map(|(name, param)| flag(name, param))
+        .collect::<Vec<_>>();
+    let width = flags.iter().map(String::len).max().unwrap();
+    usage.push_str("\n\nParams:");
+    task.params
+        .iter()
+        .zip(flags)
+        .for_each(|((name, param), flag)| {
+            let mut line = format!("\n    {:width$}    ", flag, width = width);
+            if let Some(ref help) = param.help {
+                line.push_str(help);
+                line.push(' ');
+            }
+            if let Some(ref default) = param.default {
+                line.push_str(&format!("[default: {}] ", default.to_text()));
+            }
+            if let Some(ref choices) = param.choices {
+                line.push_str(&format!("[choices: {}] ", choices.join(", ")));
+            }
+            line.push_str(&format!("[env: {}]", param_env_name(name)));
+            usage.push_str(&line);
+        });
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dorsfile::Dorsfile;
+
+    #[test]
+    fn test_parse_params() {
+        let dorsfile = Dorsfile::parse(
+            r#"
+[task.deploy]
+command = "./deploy.sh"
+
+[task.deploy.params]
+target = { choices = ["staging", "prod"], help = "where to deploy" }
+retries = { type = "int", default = 3 }
+dry-run = { type = "bool" }
+"#,
+        )
+        .unwrap();
+        let task = &dorsfile.task["deploy"];
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        let (values, rest) = parse(
+            "deploy",
+            task,
+            &args(&["--target", "prod", "extra", "--dry-run", "--", "--retries"]),
+        )
+        .unwrap();
+        assert_eq!(values["DORS_PARAM_TARGET"], "prod".into());
+        assert_eq!(values["DORS_PARAM_RETRIES"], "3".into());
+        assert_eq!(values["DORS_PARAM_DRY_RUN"], "true".into());
+        assert_eq!(rest, ["extra", "--retries"]);
+
+        let (values, _) = parse("deploy", task, &args(&["--target=staging"])).unwrap();
+        assert_eq!(values["DORS_PARAM_DRY_RUN"], "false".into());
+
+        [
+            &["--retries", "2"][..],
+            &["--target", "dev"][..],
+            &["--target", "prod", "--retries", "many"][..],
+            &["--target", "prod", "--force"][..],
+            &["--target"][..],
+        ]
+        .iter()
+        .for_each(|bad| {
+            assert!(matches!(
+                parse("deploy", task, &args(bad)),
+                Err(DorsError::InvalidTaskArgs { .. })
+            ))
+        });
+
+        assert_eq!(
+            usage("deploy", task).lines().next().unwrap(),
+            "dors deploy --target <string> [--retries <int>] [--dry-run] [ARGS]..."
+        );
+    }
+}
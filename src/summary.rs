@@ -0,0 +1,86 @@
+use crate::error::Error;
+use colored::Colorize;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// Result of a single fanned-out run, as shown in the summary table
+enum Outcome {
+    Success,
+    Failed(Option<i32>),
+    Errored(String),
+}
+
+struct Entry {
+    label: String,
+    outcome: Outcome,
+    duration: Duration,
+}
+
+/// Collects the outcome of every run in a fan-out so that they
+/// can be reported together once everything has finished.
+pub struct Summary {
+    task_name: String,
+    entries: Vec<Entry>,
+}
+
+impl Summary {
+    pub fn new(task_name: &str) -> Summary {
+        Summary {
+            task_name: task_name.to_string(),
+            entries: vec![],
+        }
+    }
+
+    pub fn push(
+        &mut self,
+        label: &str,
+        result: &Result<ExitStatus, Box<dyn Error>>,
+        duration: Duration,
+    ) {
+        let outcome = match result {
+            Ok(status) if status.success() => Outcome::Success,
+            Ok(status) => Outcome::Failed(status.code()),
+            Err(e) => Outcome::Errored(e.to_string()),
+        };
+        self.entries.push(Entry {
+            label: label.to_string(),
+            outcome,
+            duration,
+        });
+    }
+
+    pub fn print(&self) {
+        let width = self
+            .entries
+            .iter()
+            .map(|entry| entry.label.len())
+            .max()
+            .unwrap_or(0);
+        eprintln!(
+            "      {} Summary for {}:",
+            "[Dors]".yellow().bold(),
+            self.task_name.bold()
+        );
+        self.entries.iter().for_each(|entry| {
+            let (status, code) = match entry.outcome {
+                Outcome::Success => ("ok".green().bold(), "0".to_string()),
+                Outcome::Failed(code) => (
+                    "failed".red().bold(),
+                    code.map_or("-".to_string(), |code| code.to_string()),
+                ),
+                Outcome::Errored(_) => ("error".red().bold(), "-".to_string()),
+            };
+            eprintln!(
+                "        {:width$}  {:6}  {:>4}  {:>8.2}s",
+                entry.label,
+                status,
+                code,
+                entry.duration.as_secs_f64(),
+                width = width
+            );
+            if let Outcome::Errored(ref message) = entry.outcome {
+                eprintln!("          {}", message);
+            }
+        });
+    }
+}
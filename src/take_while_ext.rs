@@ -79,8 +79,7 @@ fn test_take_while_last() {
         Some(1)
     );
     assert_eq!(
-        (&[])
-            .iter()
+        [].iter()
             .cloned()
             .take_while_last(|i: &u8| (*i) != 0)
             .next(),
@@ -1,5 +1,5 @@
-use dors::DorsError;
-use dors::{all_tasks, run, run_with_args};
+use dors::{all_tasks, resolved_env, run, run_with_args, run_with_options, task_usage, RunOptions};
+use dors::{DorsError, UnmetEnvRequirement};
 
 #[test]
 fn test_workspace_only() {
@@ -9,6 +9,10 @@ fn test_workspace_only() {
         "should-run-before-only-once",
         "should-run-after-only-once",
         "should-not-run-befores-on-members",
+        "should-run-in-member",
+        "should-run-in-paths",
+        "should-run-in-glob",
+        "should-run-from-dorsfile-relative-path",
     ]
     .iter()
     .for_each(|task| assert!(run(task, "./tests/workspace_only").unwrap().success()));
@@ -16,14 +20,41 @@ fn test_workspace_only() {
 
 #[test]
 fn test_workspace_failures() {
-    ["should-fail", "should-fail-in-multiline"]
-        .iter()
-        .for_each(|task| {
-            assert_eq!(
-                run(task, "./tests/workspace_only").unwrap().code().unwrap(),
-                55
-            )
-        });
+    [
+        "should-fail",
+        "should-fail-in-multiline",
+        "should-fail-in-matrix-cell",
+        "should-fail-in-second-path",
+    ]
+    .iter()
+    .for_each(|task| {
+        assert_eq!(
+            run(task, "./tests/workspace_only").unwrap().code().unwrap(),
+            55
+        )
+    });
+}
+
+#[test]
+fn test_no_run_paths() {
+    let err = run("glob-matching-nothing", "tests/workspace_only").unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::NoRunPaths(task_name) if task_name == "glob-matching-nothing"
+    ));
+}
+
+#[test]
+fn test_missing_run_path() {
+    let err = run(
+        "should-run-from-invocation-relative-path",
+        "tests/workspace_only",
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::MissingRunPath { path, .. } if path.ends_with("workspace_only/src")
+    ));
 }
 
 #[test]
@@ -55,6 +86,7 @@ fn test_member_only() {
         "should-be-one",
         "should-be-one-at-root",
         "should-have-default-env",
+        "should-quote-env",
     ]
     .iter()
     .for_each(|task| {
@@ -75,6 +107,9 @@ fn test_workspace_all() {
         "only-member2",
         "should-inherit-envs",
         "should-have-no-args",
+        "should-have-member-env",
+        "should-have-path-entries",
+        "should-run-as-member",
     ]
     .iter()
     .for_each(|task| {
@@ -94,6 +129,110 @@ fn test_workspace_all_args() {
     .success());
 }
 
+#[test]
+fn test_dependency_args() {
+    assert!(run_with_args(
+        "should-pass-args-to-befores",
+        "tests/workspace_only",
+        &["c".to_string()]
+    )
+    .unwrap()
+    .success());
+}
+
+#[test]
+fn test_qualified_dependencies() {
+    ["tests/workspace_only", "tests/workspace_only/member1"]
+        .iter()
+        .for_each(|dir| {
+            assert!(run("should-run-qualified-dependencies", dir)
+                .unwrap()
+                .success())
+        });
+
+    assert!(
+        run("should-run-colon-named-dependency", "tests/workspace_only")
+            .unwrap()
+            .success()
+    );
+
+    // `count-runs` and `//:count-runs` are the same task when ran from the root
+    let marker = "tests/workspace_only/count-runs-ran";
+    assert!(run("should-run-root-task-once", "tests/workspace_only")
+        .unwrap()
+        .success());
+    assert_eq!(std::fs::read_to_string(marker).unwrap(), "ran\n");
+    std::fs::remove_file(marker).unwrap();
+
+    let err = run("misspelled-qualified-dependency", "tests/workspace_only").unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::UnknownMember { member, suggestion: Some(suggestion), .. }
+            if member == "membr1" && suggestion == "member1"
+    ));
+}
+
+#[test]
+fn test_task_params() {
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    assert!(run_with_args(
+        "should-parse-params",
+        "tests/workspace_only",
+        &args(&["--dry-run", "--target", "prod", "extra"])
+    )
+    .unwrap()
+    .success());
+
+    let err = run_with_args(
+        "should-parse-params",
+        "tests/workspace_only",
+        &args(&["--target", "dev"]),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::InvalidTaskArgs { task, .. } if task == "should-parse-params"
+    ));
+
+    // arguments are checked before any dependency runs
+    assert!(matches!(
+        run_with_args(
+            "should-check-params-first",
+            "tests/workspace_only",
+            &args(&["--target", "dev"]),
+        )
+        .unwrap_err()
+        .kind(),
+        DorsError::InvalidTaskArgs { .. }
+    ));
+    assert!(!std::path::Path::new("tests/workspace_only/params-checked-ran").exists());
+
+    let usage = task_usage(
+        "should-parse-params",
+        "tests/workspace_only",
+        &RunOptions::default(),
+    )
+    .unwrap();
+    assert!(usage.starts_with(
+        "dors should-parse-params --target <string> [--retries <int>] [--dry-run] [ARGS]..."
+    ));
+    assert!(usage.contains("where to deploy [choices: staging, prod] [env: DORS_PARAM_TARGET]"));
+
+    // everything after the task name is the task's, even flags that dors takes itself
+    assert!(std::process::Command::new(env!("CARGO_BIN_EXE_dors"))
+        .args([
+            "--no-user-dorsfile",
+            "should-take-dors-flags-as-params",
+            "--env",
+            "prod",
+            "--keep-going"
+        ])
+        .current_dir("tests/workspace_only")
+        .status()
+        .unwrap()
+        .success());
+}
+
 #[test]
 fn test_workspace_all_failures() {
     ["should-overwrite", "should-fail", "should-pass-args"]
@@ -115,6 +254,59 @@ fn test_workspace_all_failures() {
     });
 }
 
+#[test]
+fn test_workspace_all_keep_going() {
+    let marker = "tests/workspace_all/member2/keep-going-from-cli-ran";
+    assert_eq!(
+        run("keep-going-from-cli", "tests/workspace_all")
+            .unwrap()
+            .code()
+            .unwrap(),
+        55
+    );
+    assert!(!std::path::Path::new(marker).exists());
+
+    let options = RunOptions {
+        keep_going: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_options("keep-going-from-cli", "tests/workspace_all", &[], &options)
+            .unwrap()
+            .code()
+            .unwrap(),
+        55
+    );
+    assert!(std::path::Path::new(marker).exists());
+    std::fs::remove_file(marker).unwrap();
+
+    let marker = "tests/workspace_all/member2/keep-going-from-dorsfile-ran";
+    assert_eq!(
+        run("keep-going-from-dorsfile", "tests/workspace_all")
+            .unwrap()
+            .code()
+            .unwrap(),
+        55
+    );
+    assert!(std::path::Path::new(marker).exists());
+    std::fs::remove_file(marker).unwrap();
+}
+
+#[test]
+fn test_feature_matrix() {
+    assert!(run("feature-matrix", "tests/workspace_all")
+        .unwrap()
+        .success());
+    let ran = |member| {
+        let marker = format!("tests/workspace_all/{}/feature-matrix-ran", member);
+        let contents = std::fs::read_to_string(&marker).unwrap();
+        std::fs::remove_file(&marker).unwrap();
+        contents
+    };
+    assert_eq!(ran("member1"), "[]\n[first]\n[second]\n[first,second]\n");
+    assert_eq!(ran("member2"), "[]\n");
+}
+
 #[test]
 fn test_workspace_all_member1() {
     ["should-overwrite"].iter().for_each(|task| {
@@ -124,6 +316,151 @@ fn test_workspace_all_member1() {
     });
 }
 
+#[test]
+fn test_nested_directories() {
+    [
+        "should-overwrite",
+        "should-not-overwrite",
+        "should-be-nested",
+        "should-have-no-args",
+    ]
+    .iter()
+    .for_each(|task| {
+        assert!(run(task, "./tests/workspace_all/member1/src")
+            .unwrap()
+            .success())
+    });
+    assert!(
+        run("should-be-one", "./tests/workspace_member_only/member1/src")
+            .unwrap()
+            .success()
+    );
+}
+
+#[test]
+fn test_repository_dorsfile() {
+    [
+        "should-run-from-repo",
+        "should-run-on-members-from-repo",
+        "should-be-overridden",
+    ]
+    .iter()
+    .for_each(|task| assert!(run(task, "./tests/monorepo/workspace").unwrap().success()));
+    [
+        "./tests/monorepo/workspace/member1",
+        "./tests/monorepo/workspace3/member1",
+    ]
+    .iter()
+    .for_each(|dir| {
+        assert!(run("should-run-on-members-from-repo", dir)
+            .unwrap()
+            .success())
+    });
+
+    // a workspace without a Dorsfile of its own still has the repository's tasks
+    assert!(run(
+        "should-run-on-members-from-repo",
+        "./tests/monorepo/workspace3"
+    )
+    .unwrap()
+    .success());
+    assert_eq!(
+        run("should-be-overridden", "./tests/monorepo/workspace3")
+            .unwrap()
+            .code()
+            .unwrap(),
+        55
+    );
+}
+
+#[test]
+fn test_multiple_workspaces() {
+    // workspace3 has no Dorsfile, so reaching its members is up to the declaring task
+    [
+        "should-run-in-each-workspace",
+        "should-run-across-workspace-members",
+    ]
+    .iter()
+    .for_each(|task| assert!(run(task, "./tests/monorepo").unwrap().success()));
+
+    let options = RunOptions {
+        keep_going: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        run_with_options("should-fail-in-workspace2", "tests/monorepo", &[], &options)
+            .unwrap()
+            .code()
+            .unwrap(),
+        55
+    );
+    // workspace2's requirements are checked before anything runs in workspace
+    assert!(matches!(
+        run("should-check-every-workspace-first", "tests/monorepo")
+            .unwrap_err()
+            .kind(),
+        DorsError::UnmetEnvRequirements(unmet) if unmet[0].name == "WORKSPACE2_TOKEN"
+    ));
+    assert!(!std::path::Path::new("tests/monorepo/workspace/checked-first-ran").exists());
+
+    // inherited by each workspace, it runs only from that workspace's root
+    assert!(run(
+        "should-run-in-each-workspace",
+        "./tests/monorepo/workspace2"
+    )
+    .unwrap()
+    .success());
+}
+
+#[test]
+fn test_user_dorsfile() {
+    // the user's Dorsfile is found through the environment, so use a process of its own
+    let dors_in = |dir: &str, args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dors"))
+            .args(args)
+            .current_dir(dir)
+            .env(
+                "XDG_CONFIG_HOME",
+                std::path::Path::new("tests/user_config")
+                    .canonicalize()
+                    .unwrap(),
+            )
+            .output()
+            .unwrap()
+    };
+    let dors = |args: &[&str]| dors_in("tests/workspace_only", args);
+    assert!(dors(&["should-run-from-user-dorsfile"]).status.success());
+    let listed = String::from_utf8(dors(&["-l"]).stdout).unwrap();
+    assert!(listed
+        .lines()
+        .any(|line| line == "should-run-from-user-dorsfile"));
+    // and offered along with the rest when no task is given
+    let output = dors(&[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("should-run-from-user-dorsfile"));
+
+    let output = dors(&["--no-user-dorsfile", "should-run-from-user-dorsfile"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("No task named"));
+
+    // the user's Dorsfile is enough on its own
+    assert!(
+        dors_in("tests/user_only", &["should-run-from-user-dorsfile"])
+            .status
+            .success()
+    );
+
+    // the library leaves it out unless asked
+    assert!(!all_tasks("tests/workspace_only")
+        .unwrap()
+        .iter()
+        .any(|task| task == "should-run-from-user-dorsfile"));
+}
+
 #[test]
 fn test_list_workspace_all() {
     let mut all_tasks = all_tasks("./tests/workspace_all").unwrap();
@@ -132,17 +469,27 @@ fn test_list_workspace_all() {
         all_tasks,
         [
             "check",
+            "check-overridden-env",
+            "feature-matrix",
+            "keep-going-from-cli",
+            "keep-going-from-dorsfile",
             "nested-works-with-run-variants",
             "only-member1",
             "only-member2",
+            "only-misspelled-member",
             "should-fail",
+            "should-have-member-env",
             "should-have-no-args",
+            "should-have-overridden-env",
+            "should-have-path-entries",
             "should-inherit-envs",
             "should-not-overwrite",
             "should-not-run-before-or-after-on-member",
             "should-overwrite",
             "should-overwrite-members",
             "should-pass-args",
+            "should-run-as-member",
+            "skip-all-members",
         ]
     );
 }
@@ -150,7 +497,7 @@ fn test_list_workspace_all() {
 #[test]
 fn test_list_member_only() {
     let all_tasks = all_tasks("./tests/workspace_member_only/member1").unwrap();
-    assert_eq!(all_tasks.len(), 7);
+    assert_eq!(all_tasks.len(), 10);
 }
 
 #[test]
@@ -163,14 +510,315 @@ fn test_no_task() {
 }
 
 #[test]
-fn test_workspace_only_from_member() {
-    ["should-be-on-member", "should-run-before-only-once"]
+fn test_member_filters() {
+    let err = run("only-misspelled-member", "tests/workspace_all").unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::UnknownMember { member, suggestion: Some(suggestion), .. }
+            if member == "membr1" && suggestion == "member1"
+    ));
+
+    let err = run("skip-all-members", "tests/workspace_all").unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::NoMembersMatched(task_name) if task_name == "skip-all-members"
+    ));
+}
+
+#[test]
+fn test_members_outside_workspace_root() {
+    assert!(run("members-from-member", "tests/workspace_all/member1")
+        .unwrap()
+        .success());
+
+    let err = run("members-from-member-error", "tests/workspace_all/member1").unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        DorsError::MembersOutsideWorkspaceRoot(task_name) if task_name == "members-from-member-error"
+    ));
+}
+
+#[test]
+fn test_resolved_env() {
+    let vars = resolved_env(
+        "tests/workspace_all/member2",
+        Some("should-inherit-envs"),
+        &RunOptions::default(),
+    )
+    .unwrap();
+    let numbers = vars
         .iter()
-        .for_each(|task| {
-            assert!(run(task, "./tests/workspace_only/member1")
-                .unwrap()
-                .success())
-        });
+        .filter(|var| var.name == "NUMBER")
+        .map(|var| (var.value.as_str(), var.source.as_str(), var.overridden))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        numbers,
+        [
+            ("1", "workspace Dorsfile", true),
+            ("2", "member Dorsfile", false)
+        ]
+    );
+    assert!(vars
+        .iter()
+        .any(|var| var.name == "DORS_TASK" && var.value == "should-inherit-envs"));
+
+    // a task's own PATH entries are told apart from those of its Dorsfiles
+    let vars = resolved_env(
+        "tests/workspace_all/member2",
+        Some("should-have-path-entries"),
+        &RunOptions::default(),
+    )
+    .unwrap();
+    let paths = vars
+        .iter()
+        .filter(|var| var.name == "PATH")
+        .map(|var| var.source.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        ["Dorsfile path entries", "task `should-have-path-entries`"]
+    );
+    let vars = resolved_env("tests/workspace_all/member2", None, &RunOptions::default()).unwrap();
+    assert!(vars
+        .iter()
+        .any(|var| var.name == "PATH" && var.source == "Dorsfile path entries"));
+
+    let vars = resolved_env(
+        "tests/workspace_member_only/member1",
+        None,
+        &RunOptions::default(),
+    )
+    .unwrap();
+    assert!(vars.iter().any(|var| var.name == "EVALUATED"
+        && var.value == "hi"
+        && var.source == "member Dorsfile"));
+}
+
+#[test]
+fn test_secret_env() {
+    let vars = resolved_env(
+        "tests/workspace_member_only/member1",
+        Some("should-mask-secrets"),
+        &RunOptions::default(),
+    )
+    .unwrap();
+    let key = vars.iter().find(|var| var.name == "SIGNING_KEY").unwrap();
+    assert_eq!(key.value, "sk-4f9d2c");
+    assert_eq!(key.masked_value, "***");
+    let spaced = vars.iter().find(|var| var.name == "SPACED").unwrap();
+    assert_eq!(spaced.masked_value, spaced.value);
+
+    // output is masked by dors itself, so read it from a process of its own
+    let dors = |task: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dors"))
+            .args(["--no-user-dorsfile", task])
+            .current_dir("tests/workspace_member_only/member1")
+            .output()
+            .unwrap()
+    };
+    let output = dors("should-mask-secrets");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "key=***\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .ends_with("also key=***\n"));
+
+    let output = dors("should-mask-changing-secrets");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "nonce=***\n");
+}
+
+#[test]
+fn test_env_overrides() {
+    let options = RunOptions {
+        env: vec![("NUMBER".to_string(), "4".to_string())],
+        env_files: vec!["tests/workspace_all/overrides.env".into()],
+        ..Default::default()
+    };
+    assert!(run_with_options(
+        "should-have-overridden-env",
+        "tests/workspace_all",
+        &[],
+        &options
+    )
+    .unwrap()
+    .success());
+
+    let vars = resolved_env("tests/workspace_all/member2", None, &options).unwrap();
+    let numbers = vars
+        .iter()
+        .filter(|var| var.name == "NUMBER")
+        .map(|var| (var.value.as_str(), var.source.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        numbers,
+        [
+            ("1", "workspace Dorsfile"),
+            ("2", "member Dorsfile"),
+            ("3", "env file `tests/workspace_all/overrides.env`"),
+            ("4", "command line"),
+        ]
+    );
+
+    let options = RunOptions {
+        env_files: vec!["tests/workspace_all/missing.env".into()],
+        ..Default::default()
+    };
+    assert!(matches!(
+        run_with_options("check", "tests/workspace_all", &[], &options)
+            .unwrap_err()
+            .kind(),
+        DorsError::CouldNotReadEnvFile(_)
+    ));
+
+    // names are exported as is, so only those bash accepts are allowed
+    let invalid = |options: &RunOptions| {
+        matches!(
+            run_with_options("check", "tests/workspace_all", &[], options)
+                .unwrap_err()
+                .kind(),
+            DorsError::InvalidEnvName(_)
+        )
+    };
+    assert!(invalid(&RunOptions {
+        env: vec![("X; echo INJECTED #".to_string(), "1".to_string())],
+        ..Default::default()
+    }));
+    assert!(invalid(&RunOptions {
+        env_files: vec!["tests/workspace_all/invalid-name.env".into()],
+        ..Default::default()
+    }));
+}
+
+#[test]
+fn test_requires_env() {
+    let unmet = |options: &RunOptions| match run_with_options(
+        "should-require-env",
+        "tests/workspace_only",
+        &[],
+        options,
+    )
+    .unwrap_err()
+    .kind()
+    {
+        DorsError::UnmetEnvRequirements(unmet) => unmet
+            .iter()
+            .map(|UnmetEnvRequirement { name, found, .. }| (name.clone(), found.clone()))
+            .collect::<Vec<_>>(),
+        _ => panic!("expected unmet requirements"),
+    };
+    assert_eq!(
+        unmet(&RunOptions::default()),
+        [
+            ("DEPLOY_TOKEN".to_string(), None),
+            ("DEPLOY_ENV".to_string(), None)
+        ]
+    );
+    let env = |deploy_env: &str| RunOptions {
+        env: vec![
+            ("DEPLOY_ENV".to_string(), deploy_env.to_string()),
+            ("DEPLOY_TOKEN".to_string(), "secret".to_string()),
+        ],
+        ..Default::default()
+    };
+    assert_eq!(
+        unmet(&env("dev")),
+        [("DEPLOY_ENV".to_string(), Some("dev".to_string()))]
+    );
+    assert!(run_with_options(
+        "should-require-env",
+        "tests/workspace_only",
+        &[],
+        &env("prod")
+    )
+    .unwrap()
+    .success());
+
+    // parameters are part of the environment that is checked
+    let target = |target: &str| {
+        run_with_args(
+            "should-require-param",
+            "tests/workspace_only",
+            &["--target".to_string(), target.to_string()],
+        )
+    };
+    assert!(target("prod").unwrap().success());
+    assert!(matches!(
+        target("dev").unwrap_err().kind(),
+        DorsError::UnmetEnvRequirements(_)
+    ));
+}
+
+#[test]
+fn test_extra_members() {
+    [
+        "should-run-on-every-member",
+        "should-run-on-extra-member",
+        "should-skip-extra-member",
+        "only-inherited-by-member1",
+        "disabled-in-frontend",
+        "not-inherited",
+        "should-merge-overrides",
+    ]
+    .iter()
+    .for_each(|task| {
+        assert!(run(task, "tests/workspace_extra_members")
+            .unwrap()
+            .success())
+    });
+
+    let mut tasks = all_tasks("tests/workspace_extra_members/frontend").unwrap();
+    tasks.sort();
+    assert_eq!(
+        tasks,
+        [
+            "frontend-only",
+            "should-merge-overrides",
+            "should-run-on-every-member",
+            "should-run-on-extra-member",
+            "should-skip-extra-member",
+        ]
+    );
+}
+
+#[test]
+fn test_task_inheritance() {
+    let mut tasks = all_tasks("tests/workspace_extra_members/member1").unwrap();
+    tasks.sort();
+    assert_eq!(
+        tasks,
+        [
+            "disabled-in-frontend",
+            "only-inherited-by-member1",
+            "should-merge-overrides",
+            "should-run-on-every-member",
+            "should-run-on-extra-member",
+            "should-skip-extra-member",
+        ]
+    );
+
+    let err = run(
+        "disabled-in-frontend",
+        "tests/workspace_extra_members/frontend",
+    )
+    .unwrap_err();
+    assert!(matches!(err.kind(), DorsError::NoTask(_)));
+}
+
+#[test]
+fn test_workspace_only_from_member() {
+    [
+        "should-be-on-member",
+        "should-run-before-only-once",
+        "should-run-from-dorsfile-relative-path",
+        "should-run-from-invocation-relative-path",
+    ]
+    .iter()
+    .for_each(|task| {
+        assert!(run(task, "./tests/workspace_only/member1")
+            .unwrap()
+            .success())
+    });
 }
 
 #[test]
@@ -178,14 +826,12 @@ fn test_no_dorsfiles() {
     // since writing is occurring, careful not to use ths dir outside this test!
     let tmp_file = "tests/no_dorsfiles/Dorsfile.toml";
 
-    assert!(matches!(
-        all_tasks("./tests/no_dorsfiles").unwrap_err().kind(),
-        DorsError::NoDorsfile
-    ));
+    // only the guard in tests/Dorsfile.toml applies, which has no tasks of its own
+    assert!(all_tasks("./tests/no_dorsfiles").unwrap().is_empty());
 
     assert!(matches!(
         run("", "tests/no_dorsfiles/member1").unwrap_err().kind(),
-        DorsError::NoMemberDorsfile
+        DorsError::NoTask(_)
     ));
 
     std::fs::write(tmp_file, b"invalid-syntax").unwrap();